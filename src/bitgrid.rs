@@ -0,0 +1,495 @@
+use std::borrow::Cow;
+
+use rayon::{
+    iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+use crate::{
+    decay,
+    grid::{overlap, Grid, Neighborhood},
+    rule::Rule,
+    stats::Stats,
+    topology::Topology,
+};
+
+/// A grid that packs 64 cells into every `u64`.
+///
+/// Each row starts on a new word and bit `i` of word `j` holds the cell at
+/// `x = j * 64 + i`. Bits past the end of a row are always zero.
+pub struct BitGrid {
+    words_current: Vec<u64>,
+    words_next: Vec<u64>,
+    /// The ages of the dying cells, `planes` words for every word of cells.
+    ages: Vec<u64>,
+    planes: usize,
+    width: u32,
+    height: u32,
+    row_words: usize,
+    states: u8,
+}
+
+/// One of the three rows feeding a row of the next generation, together with
+/// the cells just past its left and right ends.
+struct SourceRow<'a> {
+    words: Cow<'a, [u64]>,
+    left: u64,
+    right: u64,
+}
+
+impl BitGrid {
+    pub fn new(width: u32, height: u32, states: u8) -> Self {
+        let row_words = (width as usize).div_ceil(64);
+        let planes = decay::planes(states);
+
+        Self {
+            words_current: vec![0; row_words * height as usize],
+            words_next: vec![0; row_words * height as usize],
+            ages: vec![0; row_words * height as usize * planes],
+            planes,
+            width,
+            height,
+            row_words,
+            states,
+        }
+    }
+
+    /// The age planes of the word of cells at `index`.
+    fn word_ages(&self, index: usize) -> &[u64] {
+        &self.ages[index * self.planes..(index + 1) * self.planes]
+    }
+
+    fn row(&self, y: u32) -> &[u64] {
+        let start = y as usize * self.row_words;
+        &self.words_current[start..start + self.row_words]
+    }
+
+    fn get(&self, x: u32, y: u32) -> bool {
+        (self.row(y)[x as usize / 64] >> (x % 64)) & 1 == 1
+    }
+
+    fn get_resolved(&self, x: i64, y: i64, topology: Topology) -> u64 {
+        match topology.resolve(x, y, self.width, self.height) {
+            Some((x, y)) => self.get(x, y) as u64,
+            None => 0,
+        }
+    }
+
+    fn source_row(&self, y: i64, topology: Topology) -> SourceRow<'_> {
+        let words = if y >= 0 && y < self.height as i64 {
+            Cow::Borrowed(self.row(y as u32))
+        } else {
+            // The row is outside the grid, so gather it cell by cell from
+            // wherever the topology says it continues
+            let mut words = vec![0; self.row_words];
+
+            for x in 0..self.width {
+                words[x as usize / 64] |= self.get_resolved(x as i64, y, topology) << (x % 64);
+            }
+
+            Cow::Owned(words)
+        };
+
+        SourceRow {
+            words,
+            left: self.get_resolved(-1, y, topology),
+            right: self.get_resolved(self.width as i64, y, topology),
+        }
+    }
+
+    fn step_row(
+        &self,
+        y: u32,
+        out: &mut [u64],
+        rule: &Rule,
+        topology: Topology,
+        neighborhood: Neighborhood,
+    ) {
+        let rows = [
+            self.source_row(y as i64 - 1, topology),
+            self.source_row(y as i64, topology),
+            self.source_row(y as i64 + 1, topology),
+        ];
+
+        let west_east = diagonal_masks(neighborhood, y as i64);
+
+        let last = self.row_words - 1;
+        let remainder = self.width % 64;
+
+        for (j, out) in out.iter_mut().enumerate() {
+            // Every row shifted so that bit `i` holds the neighbor to the left
+            // (west) or right (east) of cell `i`
+            let shifted = rows.each_ref().map(|row| {
+                let mut word = row.words[j];
+                let previous = if j == 0 {
+                    row.left
+                } else {
+                    row.words[j - 1] >> 63
+                };
+                let next = if j < last {
+                    row.words[j + 1] & 1
+                } else if remainder == 0 {
+                    row.right
+                } else {
+                    word |= row.right << remainder;
+                    0
+                };
+
+                (
+                    (word << 1) | previous,
+                    row.words[j],
+                    (word >> 1) | (next << 63),
+                )
+            });
+
+            // Dying cells can't be born until they are dead
+            let dying = decay::dying(self.word_ages(y as usize * self.row_words + j));
+            let mut next = step_word(shifted, rule, west_east) & !dying;
+
+            if j == last && remainder != 0 {
+                next &= (1 << remainder) - 1;
+            }
+
+            *out = next;
+        }
+    }
+}
+
+/// Masks for the diagonal neighbors to the left and right of the cells on row
+/// `y`, which the hexagonal neighborhood leaves out depending on the row.
+pub fn diagonal_masks(neighborhood: Neighborhood, y: i64) -> (u64, u64) {
+    match neighborhood {
+        Neighborhood::Moore => (!0, !0),
+        Neighborhood::Hexagonal if y.rem_euclid(2) == 0 => (!0, 0),
+        Neighborhood::Hexagonal => (0, !0),
+    }
+}
+
+/// The next generation of 64 cells. `rows` holds the row above, the row of
+/// the cells and the row below, each as `(west, cells, east)` where bit `i`
+/// of `west` and `east` is the neighbor to the left and right of cell `i`.
+pub fn step_word(
+    rows: [(u64, u64, u64); 3],
+    rule: &Rule,
+    (west_mask, east_mask): (u64, u64),
+) -> u64 {
+    let [(nw, n, ne), (w, alive, e), (sw, s, se)] = rows;
+    let (nw, sw) = (nw & west_mask, sw & west_mask);
+    let (ne, se) = (ne & east_mask, se & east_mask);
+
+    // Bit-sliced sum of the eight neighbors using a tree of full adders
+    let (s0, c0) = full_add(nw, n, ne);
+    let (s1, c1) = full_add(w, e, sw);
+    let (s2, c2) = (s ^ se, s & se);
+
+    let (bit0, c3) = full_add(s0, s1, s2);
+    let (t0, c4) = full_add(c0, c1, c2);
+    let (bit1, c5) = (t0 ^ c3, t0 & c3);
+    let (bit2, bit3) = (c4 ^ c5, c4 & c5);
+
+    let birth = rule.birth();
+    let survival = rule.survival();
+    let (mixed_birth, mixed_survival) = (rule.mixed_birth(), rule.mixed_survival());
+
+    let mut born = 0;
+    let mut survives = 0;
+    let mut configured = 0;
+
+    for count in 0..9 {
+        if !birth[count] && !survival[count] && !mixed_birth[count] && !mixed_survival[count] {
+            continue;
+        }
+
+        let select = |bit: u64, set: usize| if count & set != 0 { bit } else { !bit };
+        let matches = select(bit0, 1) & select(bit1, 2) & select(bit2, 4) & select(bit3, 8);
+
+        if birth[count] {
+            born |= matches;
+        }
+
+        if survival[count] {
+            survives |= matches;
+        }
+
+        // Only some configurations of this many neighbors give birth or
+        // survive, so these cells have to be told apart by where their
+        // neighbors are
+        if mixed_birth[count] {
+            configured |= matches & !alive;
+        }
+
+        if mixed_survival[count] {
+            configured |= matches & alive;
+        }
+    }
+
+    let next = (!alive & born) | (alive & survives);
+
+    if configured == 0 {
+        return next;
+    }
+
+    next | step_configurations(configured, [nw, n, ne, w, e, sw, s, se], alive, rule)
+}
+
+/// Which of `cells` are alive in the next generation for rules that tell
+/// apart the configurations of their alive neighbors, which can't be summed
+/// up by a count. The cells are split into groups by one neighbor after the
+/// other, until every group has all of its neighbors the same and is looked up
+/// at once. There are never more groups than cells, so that is at most 64
+/// lookups and a few hundred splits. `neighbors` is in the order of the bits
+/// of `neighbor_bit`.
+fn step_configurations(cells: u64, neighbors: [u64; 8], alive: u64, rule: &Rule) -> u64 {
+    fn split(cells: u64, neighbors: &[u64], configuration: u8, alive: u64, rule: &Rule) -> u64 {
+        if cells == 0 {
+            return 0;
+        }
+
+        let Some((&neighbor, rest)) = neighbors.split_first() else {
+            let born = if rule.next_state(false, configuration) {
+                !alive
+            } else {
+                0
+            };
+            let survives = if rule.next_state(true, configuration) {
+                alive
+            } else {
+                0
+            };
+            return cells & (born | survives);
+        };
+
+        let bit = 1 << (7 - rest.len());
+
+        split(cells & !neighbor, rest, configuration, alive, rule)
+            | split(cells & neighbor, rest, configuration | bit, alive, rule)
+    }
+
+    split(cells, &neighbors, 0, alive, rule)
+}
+
+#[inline(always)]
+fn full_add(a: u64, b: u64, c: u64) -> (u64, u64) {
+    let partial = a ^ b;
+    (partial ^ c, (a & b) | (partial & c))
+}
+
+impl Grid for BitGrid {
+    fn fill(&mut self, f: &(dyn Fn(u32, u32) -> bool + Sync)) {
+        let width = self.width;
+
+        self.words_current
+            .par_chunks_mut(self.row_words)
+            .enumerate()
+            .for_each(|(y, row)| {
+                row.fill(0);
+
+                for x in 0..width {
+                    row[x as usize / 64] |= (f(x, y as u32) as u64) << (x % 64);
+                }
+            });
+
+        self.ages.fill(0);
+    }
+
+    fn copy_row(&self, y: i64, left: i64, row: &mut [bool]) {
+        row.fill(false);
+
+        let Some(columns) = overlap(y, left, row.len(), self.width, self.height) else {
+            return;
+        };
+
+        for x in columns {
+            row[(x - left) as usize] = self.get(x as u32, y as u32);
+        }
+    }
+
+    fn copy_states(&self, y: i64, left: i64, row: &mut [u8]) {
+        row.fill(0);
+
+        let Some(columns) = overlap(y, left, row.len(), self.width, self.height) else {
+            return;
+        };
+
+        for x in columns {
+            let index = y as usize * self.row_words + x as usize / 64;
+            row[(x - left) as usize] = decay::state(
+                self.words_current[index],
+                self.word_ages(index),
+                (x % 64) as u32,
+            );
+        }
+    }
+
+    fn states(&self) -> u8 {
+        self.states
+    }
+
+    fn bounds(&self) -> (i64, i64, u64, u64) {
+        (0, 0, self.width as u64, self.height as u64)
+    }
+
+    fn set(&mut self, x: i64, y: i64, alive: bool) {
+        if overlap(y, x, 1, self.width, self.height).is_none() {
+            return;
+        }
+
+        let index = y as usize * self.row_words + x as usize / 64;
+        let bit = 1 << (x % 64);
+
+        if alive {
+            self.words_current[index] |= bit;
+        } else {
+            self.words_current[index] &= !bit;
+        }
+
+        for plane in &mut self.ages[index * self.planes..(index + 1) * self.planes] {
+            *plane &= !bit;
+        }
+    }
+
+    fn population(&self) -> u64 {
+        self.words_current
+            .par_iter()
+            .map(|word| word.count_ones() as u64)
+            .sum()
+    }
+
+    fn step(&mut self, rule: &Rule, topology: Topology, neighborhood: Neighborhood) -> Stats {
+        let mut words_next = std::mem::take(&mut self.words_next);
+        let row_words = self.row_words;
+
+        let stats = words_next
+            .par_chunks_mut(row_words.max(1))
+            .enumerate()
+            .map(|(y, out)| {
+                self.step_row(y as u32, out, rule, topology, neighborhood);
+
+                let old = &self.words_current[y * row_words..(y + 1) * row_words];
+                Stats::of_words(y as i64, 0, old, out)
+            })
+            .reduce(Stats::default, Stats::merge);
+
+        // Every word ages on its own, so this can happen in place, while the
+        // previous generation is still around to tell which cells just died
+        if self.planes > 0 {
+            let (words_old, words_new) = (&self.words_current, &words_next);
+
+            self.ages
+                .par_chunks_mut(self.planes)
+                .enumerate()
+                .for_each(|(index, ages)| {
+                    let died = words_old[index] & !words_new[index];
+                    decay::step(ages, died, self.states);
+                });
+        }
+
+        self.words_next = words_next;
+        std::mem::swap(&mut self.words_current, &mut self.words_next);
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::ByteGrid;
+
+    /// Widths on both sides of the word boundaries, where the cells of one
+    /// word see those of the next.
+    const WIDTHS: [u32; 4] = [63, 64, 65, 130];
+
+    const TOPOLOGIES: [Topology; 5] = [
+        Topology::Plane,
+        Topology::Torus,
+        Topology::KleinBottle,
+        Topology::CrossSurface,
+        Topology::Sphere,
+    ];
+
+    /// A random soup with about a third of the cells alive.
+    fn soup(x: u32, y: u32) -> bool {
+        let hash = (x as u64 ^ (y as u64) << 32).wrapping_mul(0x9E3779B97F4A7C15);
+        (hash >> 32).is_multiple_of(3)
+    }
+
+    /// Steps the same soup on a `ByteGrid` and a `BitGrid`, checking that every
+    /// generation and its stats come out the same.
+    fn assert_same_as_bytes(
+        width: u32,
+        height: u32,
+        rule: &str,
+        topology: Topology,
+        neighborhood: Neighborhood,
+    ) {
+        let rule: Rule = rule.parse().unwrap();
+
+        let mut bytes = ByteGrid::new(width, height, rule.states());
+        let mut bits = BitGrid::new(width, height, rule.states());
+        bytes.fill(&soup);
+        bits.fill(&soup);
+
+        let mut expected = vec![0; width as usize];
+        let mut actual = vec![0; width as usize];
+
+        for generation in 1..=40 {
+            let context = format!(
+                "{width}x{height} {rule}:{topology} {neighborhood:?}, generation {generation}"
+            );
+
+            assert_eq!(
+                bits.step(&rule, topology, neighborhood),
+                bytes.step(&rule, topology, neighborhood),
+                "stats of {context}"
+            );
+
+            for y in 0..height as i64 {
+                bytes.copy_states(y, 0, &mut expected);
+                bits.copy_states(y, 0, &mut actual);
+                assert_eq!(actual, expected, "row {y} of {context}");
+            }
+        }
+    }
+
+    #[test]
+    fn every_topology_matches_bytes() {
+        for topology in TOPOLOGIES {
+            for width in WIDTHS {
+                assert_same_as_bytes(width, 37, "B3/S23", topology, Neighborhood::Moore);
+                assert_same_as_bytes(width, 37, "B36/S125", topology, Neighborhood::Moore);
+            }
+        }
+    }
+
+    #[test]
+    fn generations_rule_matches_bytes() {
+        for topology in TOPOLOGIES {
+            for width in WIDTHS {
+                assert_same_as_bytes(width, 37, "B2/S/C3", topology, Neighborhood::Moore);
+                assert_same_as_bytes(width, 37, "345/2/6", topology, Neighborhood::Moore);
+            }
+        }
+    }
+
+    #[test]
+    fn hensel_rule_matches_bytes() {
+        for topology in TOPOLOGIES {
+            for width in WIDTHS {
+                assert_same_as_bytes(width, 37, "B2-a/S12", topology, Neighborhood::Moore);
+                assert_same_as_bytes(width, 37, "B3/S2-i34q/C4", topology, Neighborhood::Moore);
+            }
+        }
+    }
+
+    #[test]
+    fn hexagonal_neighborhood_matches_bytes() {
+        for topology in TOPOLOGIES {
+            for width in WIDTHS {
+                for rule in ["B2/S34", "B3/S23", "B2/S/C3", "B2-a/S12"] {
+                    assert_same_as_bytes(width, 37, rule, topology, Neighborhood::Hexagonal);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::{
+    color::Pipeline,
+    grid::{Backend, Grid},
+    life::Config,
+    settings::Settings,
+    subpixel::{self, Geometry, Layout},
+    viewport::Viewport,
+};
+
+/// The test patterns shown in calibration mode. Each one only looks right when
+/// the layout matches the monitor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TestPattern {
+    /// Every other subpixel, which should look like even, fine lines.
+    Stripes,
+    /// Every other subpixel in a checkerboard, which should look like an even gray.
+    Checkerboard,
+    /// Lines a single subpixel wide moving one subpixel per frame, which
+    /// should glide smoothly instead of jumping back and forth.
+    Walking,
+}
+
+impl TestPattern {
+    const ALL: [Self; 3] = [Self::Stripes, Self::Checkerboard, Self::Walking];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Stripes => "stripes",
+            Self::Checkerboard => "checkerboard",
+            Self::Walking => "walking",
+        }
+    }
+}
+
+/// Shows test patterns for checking that the configured layout matches the
+/// monitor, and saves the layout once it does.
+pub struct Calibration {
+    layout: Layout,
+    pub geometry: Geometry,
+    pattern: TestPattern,
+    grid: Box<dyn Grid>,
+    backend: Backend,
+    colors: Pipeline,
+    /// The size of the window in pixels.
+    window: (u32, u32),
+    frame: u64,
+}
+
+impl Calibration {
+    pub fn new(width: u32, height: u32, config: &Config) -> Self {
+        let geometry = config.layout.geometry();
+        let (grid_width, grid_height) = geometry.grid_size(width, height);
+
+        let mut calibration = Self {
+            layout: config.layout,
+            geometry,
+            pattern: TestPattern::Stripes,
+            grid: config.backend.create(grid_width, grid_height, 2),
+            backend: config.backend,
+            colors: Pipeline::new(&config.profile),
+            window: (width, height),
+            frame: 0,
+        };
+
+        calibration.fill();
+        calibration
+    }
+
+    /// Switches to the layout `offset` places further in `Layout::ALL`, wrapping around.
+    pub fn cycle_layout(&mut self, offset: isize) {
+        self.layout = cycle(&Layout::ALL, self.layout, offset);
+        self.geometry = self.layout.geometry();
+
+        let (width, height) = self.geometry.grid_size(self.window.0, self.window.1);
+        self.grid = self.backend.create(width, height, 2);
+        self.fill();
+    }
+
+    /// Switches to the pattern `offset` places further, wrapping around.
+    pub fn cycle_pattern(&mut self, offset: isize) {
+        self.pattern = cycle(&TestPattern::ALL, self.pattern, offset);
+        self.fill();
+    }
+
+    pub fn advance(&mut self) {
+        self.frame += 1;
+        self.fill();
+    }
+
+    /// Remembers the current layout so that it is used from now on.
+    pub fn save(&self) {
+        let settings = Settings {
+            layout: Some(self.layout),
+        };
+
+        match settings.save() {
+            Ok(path) => println!("saved layout {} to {}", self.layout, path.display()),
+            Err(err) => eprintln!("could not save the layout: {err}"),
+        }
+    }
+
+    pub fn title(&self) -> String {
+        format!(
+            "Subpixel calibration: {} layout, {} (Left/Right: layout, Up/Down: pattern, Enter: save)",
+            self.layout,
+            self.pattern.name()
+        )
+    }
+
+    pub fn render(&self, pixels: &mut [u32], width: u32, height: u32) {
+        subpixel::render(
+            self.grid.as_ref(),
+            &self.geometry,
+            &Viewport::default(),
+            &self.colors,
+            pixels,
+            width,
+            height,
+        );
+    }
+
+    fn fill(&mut self) {
+        // Patterns run along the direction the subpixels of a pixel are lined up in
+        let (cells_x, cells_y) = self.geometry.cells_per_pixel;
+        let horizontal = cells_x >= cells_y;
+        let along = move |x: u32, y: u32| if horizontal { x } else { y };
+
+        let frame = self.frame;
+
+        match self.pattern {
+            TestPattern::Stripes => self.grid.fill(&|x, y| along(x, y) % 2 == 0),
+            TestPattern::Checkerboard => self.grid.fill(&|x, y| (x + y) % 2 == 0),
+            // Six apart so that a correct layout shows every color in turn
+            TestPattern::Walking => self.grid.fill(&|x, y| along(x, y) as u64 % 6 == frame % 6),
+        }
+    }
+}
+
+fn cycle<T: Copy + PartialEq>(all: &[T], current: T, offset: isize) -> T {
+    let index = all.iter().position(|item| *item == current).unwrap_or(0);
+    all[(index as isize + offset).rem_euclid(all.len() as isize) as usize]
+}
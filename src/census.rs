@@ -0,0 +1,625 @@
+//! A census of what random soups settle into, in the style of apgsearch. See
+//! <https://conwaylife.com/wiki/Apgsearch>.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    grid::alive_cells,
+    life::{Config, GameOfLife, Size},
+    rule::{neighbor_bit, Rule},
+    spaceships,
+};
+
+/// The longest period an object is stepped for before giving up on it.
+const MAX_PERIOD: u64 = 1024;
+
+/// Objects that grow past this many cells on their own are given up on.
+const MAX_POPULATION: usize = 10_000;
+
+/// The longest population period that counts as settled, enough for every
+/// combination of the common oscillators and gliders.
+const MAX_POPULATION_PERIOD: usize = 60;
+
+/// How many generations the population has to repeat for before a soup counts
+/// as settled.
+const SETTLED_WINDOW: usize = 300;
+
+/// Names of the objects that turn up most often.
+const NAMES: [(&str, &str); 16] = [
+    ("xs4_33", "block"),
+    ("xp2_7", "blinker"),
+    ("xs6_696", "beehive"),
+    ("xq4_153", "glider"),
+    ("xs7_2596", "loaf"),
+    ("xs5_253", "boat"),
+    ("xs4_252", "tub"),
+    ("xs8_6996", "pond"),
+    ("xs6_356", "ship"),
+    ("xp2_7e", "toad"),
+    ("xp2_318c", "beacon"),
+    ("xs6_25a4", "barge"),
+    ("xs7_25ac", "long boat"),
+    ("xq4_6frc", "lightweight spaceship"),
+    ("xq4_27dee6", "middleweight spaceship"),
+    ("xp3_co9nas0san9oczgoldlo0oldlogz1047210127401", "pulsar"),
+];
+
+/// What an object does when left on its own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Behavior {
+    /// Returns to the same cells every `period` generations, 1 for still lifes.
+    Oscillator { period: u64 },
+    /// Returns to the same shape moved by `(dx, dy)` every `period` generations.
+    Spaceship { period: u64, dx: i64, dy: i64 },
+    /// Doesn't repeat within the generations it was given.
+    Unknown,
+}
+
+/// An object with its apgcode, e.g. `xs4_33` for a block or `xq4_153` for a
+/// glider, which is the same in every phase and orientation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Classification {
+    pub apgcode: String,
+    pub behavior: Behavior,
+}
+
+/// Splits alive cells into objects, cells up to two apart horizontally and
+/// vertically being part of the same object.
+pub fn separate(cells: &[(i64, i64)]) -> Vec<Vec<(i64, i64)>> {
+    let index: HashMap<(i64, i64), usize> = cells
+        .iter()
+        .enumerate()
+        .map(|(index, cell)| (*cell, index))
+        .collect();
+
+    // Union-find with path halving
+    let mut parents: Vec<usize> = (0..cells.len()).collect();
+
+    fn find(parents: &mut [usize], mut cell: usize) -> usize {
+        while parents[cell] != cell {
+            parents[cell] = parents[parents[cell]];
+            cell = parents[cell];
+        }
+
+        cell
+    }
+
+    for (cell, &(x, y)) in cells.iter().enumerate() {
+        for dy in -2..=2 {
+            for dx in -2..=2 {
+                if let Some(&other) = index.get(&(x + dx, y + dy)) {
+                    let (a, b) = (find(&mut parents, cell), find(&mut parents, other));
+                    parents[a] = b;
+                }
+            }
+        }
+    }
+
+    let mut objects: HashMap<usize, Vec<(i64, i64)>> = HashMap::new();
+
+    for (cell, position) in cells.iter().enumerate() {
+        let root = find(&mut parents, cell);
+        objects.entry(root).or_default().push(*position);
+    }
+
+    objects.into_values().collect()
+}
+
+/// Steps an object on its own until it repeats and names it.
+pub fn classify(cells: &[(i64, i64)], rule: &Rule) -> Classification {
+    classify_within(cells, rule, MAX_PERIOD, MAX_POPULATION)
+}
+
+/// Like `classify`, but gives up on objects that don't repeat within
+/// `max_period` generations or grow past `max_population` cells.
+pub fn classify_within(
+    cells: &[(i64, i64)],
+    rule: &Rule,
+    max_period: u64,
+    max_population: usize,
+) -> Classification {
+    let (start_origin, start) = normalize(cells.iter().copied());
+    let mut phases = vec![start.clone()];
+    let mut current: HashSet<(i64, i64)> = cells.iter().copied().collect();
+
+    for period in 1..=max_period {
+        current = step_cells(&current, rule);
+
+        if current.is_empty() || current.len() > max_population {
+            break;
+        }
+
+        let (origin, shape) = normalize(current.iter().copied());
+
+        if shape == start {
+            let (dx, dy) = (origin.0 - start_origin.0, origin.1 - start_origin.1);
+
+            let (prefix, behavior) = if (dx, dy) != (0, 0) {
+                (
+                    format!("xq{period}"),
+                    Behavior::Spaceship { period, dx, dy },
+                )
+            } else if period == 1 {
+                (
+                    format!("xs{}", start.len()),
+                    Behavior::Oscillator { period },
+                )
+            } else {
+                (format!("xp{period}"), Behavior::Oscillator { period })
+            };
+
+            return Classification {
+                apgcode: format!("{prefix}_{}", canonical_code(&phases)),
+                behavior,
+            };
+        }
+
+        phases.push(shape);
+    }
+
+    Classification {
+        apgcode: "PATHOLOGICAL".to_string(),
+        behavior: Behavior::Unknown,
+    }
+}
+
+/// The common name of an object, if it has one.
+pub fn name(apgcode: &str) -> Option<&'static str> {
+    NAMES
+        .iter()
+        .find(|(code, _)| *code == apgcode)
+        .map(|(_, name)| *name)
+}
+
+/// The next generation of a set of alive cells on an unbounded plane.
+fn step_cells(cells: &HashSet<(i64, i64)>, rule: &Rule) -> HashSet<(i64, i64)> {
+    // The alive neighbors of every cell next to one, as by `neighbor_bit`
+    let mut neighbors: HashMap<(i64, i64), u8> = HashMap::new();
+
+    for &(x, y) in cells {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                if (dx, dy) != (0, 0) {
+                    // Seen from the neighbor, this cell is the other way round
+                    *neighbors.entry((x + dx, y + dy)).or_default() |= neighbor_bit(-dx, -dy);
+                }
+            }
+        }
+    }
+
+    // Cells without any neighbors aren't in `neighbors`, but can survive with S0
+    let candidates: HashSet<(i64, i64)> = neighbors.keys().chain(cells).copied().collect();
+
+    candidates
+        .into_iter()
+        .filter(|cell| {
+            rule.next_state(
+                cells.contains(cell),
+                neighbors.get(cell).copied().unwrap_or(0),
+            )
+        })
+        .collect()
+}
+
+/// Moves cells so that their bounding box starts at `(0, 0)`, returning where
+/// it was and the sorted cells.
+pub fn normalize(cells: impl Iterator<Item = (i64, i64)>) -> ((i64, i64), Vec<(i64, i64)>) {
+    let mut cells: Vec<(i64, i64)> = cells.collect();
+
+    let left = cells.iter().map(|(x, _)| *x).min().unwrap_or(0);
+    let top = cells.iter().map(|(_, y)| *y).min().unwrap_or(0);
+
+    for (x, y) in &mut cells {
+        *x -= left;
+        *y -= top;
+    }
+
+    cells.sort_unstable();
+    ((left, top), cells)
+}
+
+/// The shortest extended Wechsler code of any phase in any of the eight
+/// orientations, the alphabetically first one of those if there are several.
+fn canonical_code(phases: &[Vec<(i64, i64)>]) -> String {
+    // The rows of the 2 by 2 matrices of the rotations and reflections
+    const ORIENTATIONS: [[i64; 4]; 8] = [
+        [1, 0, 0, 1],
+        [-1, 0, 0, 1],
+        [1, 0, 0, -1],
+        [-1, 0, 0, -1],
+        [0, 1, 1, 0],
+        [0, -1, 1, 0],
+        [0, 1, -1, 0],
+        [0, -1, -1, 0],
+    ];
+
+    phases
+        .iter()
+        .flat_map(|phase| {
+            ORIENTATIONS.iter().map(|[a, b, c, d]| {
+                let (_, cells) =
+                    normalize(phase.iter().map(|&(x, y)| (a * x + b * y, c * x + d * y)));
+                wechsler(&cells)
+            })
+        })
+        .min_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+        .unwrap_or_default()
+}
+
+/// The extended Wechsler code of cells with their bounding box at `(0, 0)`:
+/// strips of five rows separated by `z`, each a column at a time as a digit of
+/// base 32 with the top row as the lowest bit, runs of zeros shortened to `w`,
+/// `x` or `y` and a count, and the zeros at the end of each strip left out.
+fn wechsler(cells: &[(i64, i64)]) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+    let width = cells.iter().map(|(x, _)| x + 1).max().unwrap_or(0) as usize;
+    let height = cells.iter().map(|(_, y)| y + 1).max().unwrap_or(0) as usize;
+
+    let mut strips = vec![vec![0u8; width]; height.div_ceil(5)];
+    for &(x, y) in cells {
+        strips[y as usize / 5][x as usize] |= 1 << (y % 5);
+    }
+
+    let mut code = String::new();
+
+    for (index, strip) in strips.iter().enumerate() {
+        if index > 0 {
+            code.push('z');
+        }
+
+        let end = strip
+            .iter()
+            .rposition(|column| *column != 0)
+            .map_or(0, |last| last + 1);
+        let mut columns = strip[..end].iter().peekable();
+
+        while let Some(&column) = columns.next() {
+            if column != 0 {
+                code.push(DIGITS[column as usize] as char);
+                continue;
+            }
+
+            let mut zeros = 1;
+            while columns.next_if(|column| **column == 0).is_some() {
+                zeros += 1;
+            }
+
+            while zeros > 0 {
+                let run = zeros.min(39);
+
+                match run {
+                    1 => code.push('0'),
+                    2 => code.push('w'),
+                    3 => code.push('x'),
+                    run => {
+                        code.push('y');
+                        code.push(DIGITS[run - 4] as char);
+                    }
+                }
+
+                zeros -= run;
+            }
+        }
+    }
+
+    code
+}
+
+/// Whether the last `SETTLED_WINDOW` populations repeat with a short period,
+/// which is how a soup looks once only still lifes, oscillators and escaping
+/// spaceships are left.
+fn population_settled(populations: &[u64]) -> bool {
+    if populations.len() < SETTLED_WINDOW + MAX_POPULATION_PERIOD {
+        return false;
+    }
+
+    let recent = &populations[populations.len() - SETTLED_WINDOW - MAX_POPULATION_PERIOD..];
+
+    (1..=MAX_POPULATION_PERIOD).any(|period| {
+        (recent.len() - SETTLED_WINDOW..recent.len())
+            .all(|generation| recent[generation] == recent[generation - period])
+    })
+}
+
+/// What one soup settled into.
+struct SoupCensus {
+    /// How many there are of every object, and what one of them does.
+    objects: HashMap<String, (u64, Behavior)>,
+    settled: bool,
+}
+
+/// Steps a soup until it settles or `generations` have passed, and counts
+/// the objects in it.
+fn census_soup(seed: u64, generations: u64, config: &Config) -> SoupCensus {
+    let Size::Cells(width, height) = config.size else {
+        unreachable!("census mode always has a size");
+    };
+
+    let mut game = GameOfLife::new(width, height, config);
+    game.seed = seed;
+    game.populate(config);
+
+    let mut populations = vec![game.stats.population];
+    let mut settled = false;
+
+    while game.generation < generations {
+        game.step();
+        populations.push(game.stats.population);
+
+        if game.cycle.is_some() || population_settled(&populations) {
+            settled = true;
+            break;
+        }
+    }
+
+    let mut objects = HashMap::new();
+
+    for object in separate(&alive_cells(game.grid.as_ref())) {
+        let classification = classify(&object, &game.rule);
+        objects
+            .entry(classification.apgcode)
+            .or_insert((0, classification.behavior))
+            .0 += 1;
+    }
+
+    SoupCensus { objects, settled }
+}
+
+/// Every object found, with how often it turned up and what it does.
+type Totals = Vec<(String, (u64, Behavior))>;
+
+/// How often every object turned up in `soups` soups with consecutive seeds
+/// from `first_seed` on, along with what it does, most common first, and how
+/// many of the soups hadn't settled after `generations`.
+fn tally(first_seed: u64, soups: u64, generations: u64, config: &Config) -> (Totals, usize) {
+    let censuses: Vec<SoupCensus> = (0..soups)
+        .into_par_iter()
+        .map(|soup| census_soup(first_seed.wrapping_add(soup), generations, config))
+        .collect();
+
+    let mut totals: HashMap<String, (u64, Behavior)> = HashMap::new();
+    for census in &censuses {
+        for (apgcode, (count, behavior)) in &census.objects {
+            totals.entry(apgcode.clone()).or_insert((0, *behavior)).0 += count;
+        }
+    }
+
+    let mut totals: Totals = totals.into_iter().collect();
+    totals.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then_with(|| a.0.cmp(&b.0)));
+
+    let unsettled = censuses.iter().filter(|census| !census.settled).count();
+
+    (totals, unsettled)
+}
+
+/// Runs `soups` random soups with consecutive seeds until each settles, giving
+/// up after `generations`, and prints how often every object turned up.
+pub fn run(soups: u64, generations: u64, config: &Config) {
+    let first_seed = config.seed.unwrap_or_else(rand::random);
+    let (totals, unsettled) = tally(first_seed, soups, generations, config);
+
+    let Size::Cells(width, height) = config.size else {
+        unreachable!("census mode always has a size");
+    };
+
+    let mut report = format!(
+        "census of {soups} {width}x{height} soups, rule {}, seeds {first_seed} to {}\n",
+        config.rule,
+        first_seed.wrapping_add(soups.saturating_sub(1))
+    );
+
+    for (apgcode, (count, behavior)) in &totals {
+        write!(report, "{count:>10}  {apgcode}").unwrap();
+
+        if let Some(name) = name(apgcode) {
+            write!(report, " ({name})").unwrap();
+        }
+
+        // The same spaceship flies in all directions, so its speed is given
+        // the way round that is the same for all of them
+        if let Behavior::Spaceship { period, dx, dy } = *behavior {
+            let (dx, dy) = (dx.abs().max(dy.abs()), dx.abs().min(dy.abs()));
+            write!(report, " moving at {}", spaceships::speed(dx, dy, period)).unwrap();
+        }
+
+        report.push('\n');
+    }
+
+    if unsettled > 0 {
+        writeln!(
+            report,
+            "{unsettled} soups had not settled after {generations} generations"
+        )
+        .unwrap();
+    }
+
+    print!("{report}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{color::Profile, grid::Backend, subpixel::Layout, topology::Topology};
+
+    /// The alive cells of a picture with `O` for alive.
+    fn cells(rows: &[&str]) -> Vec<(i64, i64)> {
+        rows.iter()
+            .enumerate()
+            .flat_map(|(y, row)| {
+                row.chars()
+                    .enumerate()
+                    .filter(|(_, cell)| *cell == 'O')
+                    .map(move |(x, _)| (x as i64, y as i64))
+            })
+            .collect()
+    }
+
+    /// A cell or a velocity in one of the 8 ways of turning and mirroring it,
+    /// from 0 for as it is to 7.
+    fn orient(orientation: u8, (x, y): (i64, i64)) -> (i64, i64) {
+        let (x, y) = if orientation & 4 != 0 { (y, x) } else { (x, y) };
+        let x = if orientation & 1 != 0 { -x } else { x };
+        let y = if orientation & 2 != 0 { -y } else { y };
+        (x, y)
+    }
+
+    /// Checks that `object` is classified the same way in every orientation
+    /// and phase away from the origin, moving as `behavior` says when it isn't
+    /// turned.
+    fn assert_classified(object: &[(i64, i64)], apgcode: &str, behavior: Behavior) {
+        let rule = Rule::default();
+
+        for orientation in 0..8 {
+            let mut phase: HashSet<_> = object
+                .iter()
+                .map(|cell| orient(orientation, *cell))
+                .map(|(x, y)| (x + 1000, y - 300))
+                .collect();
+
+            let expected = match behavior {
+                Behavior::Spaceship { period, dx, dy } => {
+                    let (dx, dy) = orient(orientation, (dx, dy));
+                    Behavior::Spaceship { period, dx, dy }
+                }
+                behavior => behavior,
+            };
+
+            for _ in 0..4 {
+                let cells: Vec<_> = phase.iter().copied().collect();
+                let classification = classify(&cells, &rule);
+
+                assert_eq!(classification.apgcode, apgcode);
+                assert_eq!(classification.behavior, expected);
+
+                phase = step_cells(&phase, &rule);
+            }
+        }
+    }
+
+    /// The velocity of a spaceship as the census reports it.
+    fn speed(object: &[(i64, i64)]) -> String {
+        match classify(object, &Rule::default()).behavior {
+            Behavior::Spaceship { period, dx, dy } => spaceships::speed(dx, dy, period),
+            behavior => panic!("{behavior:?} isn't a spaceship"),
+        }
+    }
+
+    #[test]
+    fn block() {
+        assert_classified(
+            &cells(&["OO", "OO"]),
+            "xs4_33",
+            Behavior::Oscillator { period: 1 },
+        );
+        assert_eq!(name("xs4_33"), Some("block"));
+    }
+
+    #[test]
+    fn blinker() {
+        assert_classified(
+            &cells(&["OOO"]),
+            "xp2_7",
+            Behavior::Oscillator { period: 2 },
+        );
+    }
+
+    #[test]
+    fn glider() {
+        let glider = cells(&[".O.", "..O", "OOO"]);
+        assert_classified(
+            &glider,
+            "xq4_153",
+            Behavior::Spaceship {
+                period: 4,
+                dx: 1,
+                dy: 1,
+            },
+        );
+        assert_eq!(speed(&glider), "(1,1)c/4");
+    }
+
+    #[test]
+    fn lightweight_spaceship() {
+        let lwss = cells(&["O..O.", "....O", "O...O", ".OOOO"]);
+        assert_classified(
+            &lwss,
+            "xq4_6frc",
+            Behavior::Spaceship {
+                period: 4,
+                dx: 2,
+                dy: 0,
+            },
+        );
+        assert_eq!(speed(&lwss), "(2,0)c/4");
+    }
+
+    #[test]
+    fn glider_gun_is_pathological() {
+        // It never repeats, it only keeps adding gliders
+        let gun = cells(&[
+            "........................O...........",
+            "......................O.O...........",
+            "............OO......OO............OO",
+            "...........O...O....OO............OO",
+            "OO........O.....O...OO..............",
+            "OO........O...O.OO....O.O...........",
+            "..........O.....O.......O...........",
+            "...........O...O....................",
+            "............OO......................",
+        ]);
+
+        let classification = classify(&gun, &Rule::default());
+        assert_eq!(classification.apgcode, "PATHOLOGICAL");
+        assert_eq!(classification.behavior, Behavior::Unknown);
+    }
+
+    #[test]
+    fn soups_tally_the_same_for_a_seed() {
+        let config = Config {
+            rule: Rule::default(),
+            topology: Topology::Plane,
+            backend: Backend::Sparse,
+            size: Size::Cells(16, 16),
+            layout: Layout::default(),
+            profile: Profile::default(),
+            density: 0.5,
+            seed: Some(1),
+            pattern: None,
+            offset: None,
+            reseed: false,
+        };
+
+        // Soups 1 to 8, as `census --soups 8 --seed 1` runs them
+        let (totals, unsettled) = tally(1, 8, 10_000, &config);
+
+        let expected = [
+            ("xs4_33", 52),
+            ("xs6_696", 24),
+            ("xp2_7", 16),
+            ("xq4_153", 13),
+            ("xs5_253", 8),
+            ("xs7_2596", 7),
+            ("xs6_356", 5),
+            ("xp2_1110s", 3),
+            ("xp2_s01110szw222", 2),
+            ("xs8_6996", 2),
+            ("xp2_033g88gzcia521we", 1),
+            ("xp2_04a96z7", 1),
+            ("xp2_1118kk8", 1),
+            ("xs11_w6952z33", 1),
+        ];
+
+        let totals: Vec<_> = totals
+            .iter()
+            .map(|(apgcode, (count, _))| (apgcode.as_str(), *count))
+            .collect();
+        assert_eq!(totals, expected);
+        assert_eq!(unsettled, 0);
+    }
+}
@@ -0,0 +1,621 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    color::Profile,
+    grid::{Backend, Neighborhood},
+    hashlife,
+    life::{Config, Size},
+    pattern::Pattern,
+    rule::Rule,
+    settings::Settings,
+    subpixel::Layout,
+    topology::Topology,
+};
+
+pub const USAGE: &str = "\
+Usage:
+    subpixel-life [OPTIONS]
+    subpixel-life headless --size WIDTHxHEIGHT --generations N [OPTIONS]
+    subpixel-life census [--soups N] [OPTIONS]
+    subpixel-life calibrate [OPTIONS]
+
+Census steps many random soups on an unbounded plane until they settle and counts the
+still lifes, oscillators and spaceships they leave behind by their apgcodes.
+
+Calibrate shows test patterns that only look right when the layout matches the monitor.
+Left and Right switch layouts, Up and Down switch patterns and Enter saves the layout
+as the default for later runs.
+
+Options:
+    -r, --rule RULE[:TOPOLOGY]  Life-like rule, e.g. B3/S23, 23/3 or B36/S23:T, Generations rule
+                                such as B2/S/C3 or 345/2/4, or isotropic non-totalistic rule in
+                                Hensel notation such as B2-a/S12 [default: B3/S23]
+    -t, --topology TOPOLOGY     P (plane), T (torus), K (Klein bottle), C (cross-surface)
+                                or S (sphere) [default: P]
+    -s, --size WIDTHxHEIGHT     Grid size in cells, or \"fit\" to fill the window with one
+                                cell per subpixel [default: fit]
+    -l, --layout LAYOUT         Subpixel layout of the monitor: rgb, bgr, v-rgb or v-bgr
+                                for stripes, where v- means stacked vertically as on a
+                                rotated monitor, rgbg for PenTile, delta or rgbw
+                                [default: the layout saved by calibrate, or rgb, which
+                                headless and census modes always use]
+        --profile FILE          Calibration profile with the gamma, brightness and channel
+                                crosstalk of the monitor [default: ideal sRGB monitor]
+    -d, --density P             Chance of each cell starting alive [default: 0.5]
+    -p, --pattern FILE          Start from an RLE, .cells or .mc pattern instead of a random soup,
+                                using the rule in the file unless --rule is given
+    -o, --offset X,Y            Where the top left of the pattern goes [default: centered]
+        --seed N                Seed for the initial random soup [default: random, printed
+                                at startup]
+    -g, --speed N               Generations per second, changed with + and - while it runs; the
+                                window is still drawn at the monitor refresh rate [default: one
+                                generation per frame]
+        --run N                 Generations G runs before pausing [default: 100]
+        --reseed                Start a new random soup a little while after the grid has
+                                settled into still lifes and oscillators
+        --window                Open a normal window instead of going fullscreen
+    -m, --monitor N             Index of the monitor to use [default: primary monitor]
+        --backend BACKEND       bytes, packed or sparse, an unbounded plane where the size only
+                                sets the area of the soup [default: packed]
+    -n, --generations N         Generations to step in headless mode, or the most to step each
+                                soup for in census mode [default for census: 10000]
+        --until-settled         Stop early in headless mode once the grid has settled, with
+                                --generations as the limit
+        --hashlife              Jump straight to the last generation with HashLife in headless
+                                mode, on an unbounded plane with the Moore neighborhood
+        --save FILE             Save the last generation in headless mode, as plaintext if
+                                FILE ends in .cells, as macrocell if it ends in .mc and as
+                                RLE otherwise
+        --screenshot FILE       Save a PNG of the last generation in headless mode, exactly
+                                as it would be drawn with one cell per subpixel
+        --subpixel-view FILE    Save a PNG of the last generation in headless mode with every
+                                pixel blown up into its separate subpixels
+        --stats FILE            Save the population, births, deaths and bounding box of every
+                                generation in headless mode, as JSON if FILE ends in .json
+                                and as CSV otherwise
+        --soups N               Number of soups in census mode, with consecutive seeds starting
+                                at --seed [default: 1000]
+        --spaceships            List the spaceships of the last generation in headless mode with
+                                their velocities, and outline them in the screenshots
+    -h, --help                  Print this help";
+
+/// Everything needed to open the window, none of which affects the simulation.
+pub struct WindowOptions {
+    pub fullscreen: bool,
+    pub monitor: Option<usize>,
+    /// The size of the window in pixels when not fullscreen, `None` for half the monitor.
+    pub size: Option<(u32, u32)>,
+    /// Generations per second, `None` for one every frame at the monitor's
+    /// refresh rate.
+    pub speed: Option<f64>,
+    /// The generations `G` runs before pausing.
+    pub run_generations: u64,
+}
+
+pub enum Mode {
+    Window(WindowOptions),
+    /// Test patterns for finding the layout of the monitor.
+    Calibrate(WindowOptions),
+    Headless {
+        generations: u64,
+        /// Where to save the final generation, as plaintext for `.cells`, macrocell for
+        /// `.mc` and RLE otherwise.
+        save: Option<PathBuf>,
+        /// Where to save a PNG of the final generation as it would be drawn.
+        screenshot: Option<PathBuf>,
+        /// Where to save a PNG of the final generation with every subpixel blown up.
+        subpixel_view: Option<PathBuf>,
+        /// Where to save the stats of every generation, as JSON for `.json` and CSV otherwise.
+        stats: Option<PathBuf>,
+        /// Whether to jump to the final generation with HashLife instead of stepping.
+        hashlife: bool,
+        /// Whether to stop as soon as the grid has settled.
+        until_settled: bool,
+        /// Whether to list and outline the spaceships of the final generation.
+        spaceships: bool,
+    },
+    /// A count of the objects that random soups settle into.
+    Census {
+        soups: u64,
+        /// The most generations to step each soup for.
+        generations: u64,
+    },
+}
+
+pub struct Options {
+    pub mode: Mode,
+    pub config: Config,
+}
+
+impl Options {
+    /// Parses the command line arguments, not including the program name.
+    ///
+    /// Returns `Ok(None)` if help was requested.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut args = args.into_iter().peekable();
+
+        let headless = args.next_if(|arg| arg == "headless").is_some();
+        let census = !headless && args.next_if(|arg| arg == "census").is_some();
+        let calibrate = !headless && !census && args.next_if(|arg| arg == "calibrate").is_some();
+
+        let mut config = Config {
+            rule: Rule::default(),
+            topology: Topology::default(),
+            backend: Backend::default(),
+            size: Size::Fit,
+            layout: Layout::default(),
+            profile: Profile::default(),
+            density: 0.5,
+            seed: None,
+            pattern: None,
+            offset: None,
+            reseed: false,
+        };
+
+        let mut pattern_path = None;
+        let mut rule_given = false;
+        let mut topology_given = false;
+        let mut layout_given = false;
+
+        let mut window = WindowOptions {
+            fullscreen: true,
+            monitor: None,
+            size: None,
+            speed: None,
+            run_generations: 100,
+        };
+
+        let mut generations = None;
+        let mut save = None;
+        let mut screenshot = None;
+        let mut subpixel_view = None;
+        let mut stats = None;
+        let mut hashlife = false;
+        let mut until_settled = false;
+        let mut soups = None;
+        let mut spaceships = false;
+
+        while let Some(arg) = args.next() {
+            // Accept both `--flag value` and `--flag=value`
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg, None),
+            };
+
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{flag} needs a value"))
+            };
+
+            match flag.as_str() {
+                "-h" | "--help" => return Ok(None),
+                "-r" | "--rule" => {
+                    // Like Golly, the topology can be appended to the rule, e.g. `B3/S23:T`
+                    let value = value()?;
+                    let (rule, topology) = value.split_once(':').unwrap_or((&value, ""));
+
+                    config.rule = parse(rule, "rule")?;
+                    rule_given = true;
+
+                    if !topology.is_empty() {
+                        config.topology = parse(topology, "topology")?;
+                        topology_given = true;
+                    }
+                }
+                "-t" | "--topology" => {
+                    config.topology = parse(&value()?, "topology")?;
+                    topology_given = true;
+                }
+                "-p" | "--pattern" => pattern_path = Some(value()?),
+                "-o" | "--offset" => {
+                    let value = value()?;
+                    let (x, y) = value
+                        .split_once(',')
+                        .ok_or_else(|| format!("Invalid offset \"{value}\": expected X,Y"))?;
+                    config.offset = Some((parse(x.trim(), "offset")?, parse(y.trim(), "offset")?));
+                }
+                "-s" | "--size" => config.size = parse(&value()?, "size")?,
+                "-d" | "--density" => {
+                    config.density = parse(&value()?, "density")?;
+
+                    if !(0.0..=1.0).contains(&config.density) {
+                        return Err(format!(
+                            "Invalid density {}: must be between 0 and 1",
+                            config.density
+                        ));
+                    }
+                }
+                "--seed" => config.seed = Some(parse(&value()?, "seed")?),
+                "-g" | "--speed" => {
+                    let speed: f64 = parse(&value()?, "speed")?;
+
+                    if !(speed > 0.0 && speed.is_finite()) {
+                        return Err(format!("Invalid speed {speed}: must be positive"));
+                    }
+
+                    window.speed = Some(speed);
+                }
+                "--run" => {
+                    window.run_generations = parse(&value()?, "generation count")?;
+
+                    if window.run_generations == 0 {
+                        return Err("--run needs at least 1 generation".to_string());
+                    }
+                }
+                "--window" => window.fullscreen = false,
+                "--fullscreen" => window.fullscreen = true,
+                "-m" | "--monitor" => window.monitor = Some(parse(&value()?, "monitor")?),
+                "-l" | "--layout" => {
+                    config.layout = parse(&value()?, "layout")?;
+                    layout_given = true;
+                }
+                "--profile" => {
+                    let path = value()?;
+                    config.profile = Profile::load(Path::new(&path))
+                        .map_err(|err| format!("Could not load profile \"{path}\": {err}"))?;
+                }
+                "--backend" => config.backend = parse(&value()?, "backend")?,
+                "--save" => save = Some(PathBuf::from(value()?)),
+                "--screenshot" => screenshot = Some(PathBuf::from(value()?)),
+                "--subpixel-view" => subpixel_view = Some(PathBuf::from(value()?)),
+                "--stats" => stats = Some(PathBuf::from(value()?)),
+                "--hashlife" => hashlife = true,
+                "--until-settled" => until_settled = true,
+                "--spaceships" => spaceships = true,
+                "--reseed" => config.reseed = true,
+                "--soups" => soups = Some(parse(&value()?, "soup count")?),
+                "-n" | "--generations" => generations = Some(parse(&value()?, "generation count")?),
+                _ => return Err(format!("Unknown argument \"{flag}\"")),
+            }
+        }
+
+        // Only the window uses the saved layout, so that headless runs and the
+        // census give the same results on every machine
+        if !layout_given && !headless && !census {
+            config.layout = Settings::load()?.layout.unwrap_or_default();
+        }
+
+        if let Some(path) = pattern_path {
+            let pattern = Pattern::load(Path::new(&path))
+                .map_err(|err| format!("Could not load pattern \"{path}\": {err}"))?;
+
+            if !rule_given {
+                config.rule = pattern.rule.unwrap_or(config.rule);
+            }
+
+            if !topology_given {
+                config.topology = pattern.topology.unwrap_or(config.topology);
+            }
+
+            config.pattern = Some(pattern);
+        }
+
+        if census {
+            if config.pattern.is_some() {
+                return Err(
+                    "Census mode only runs random soups, it can't use --pattern".to_string()
+                );
+            }
+
+            // Spaceships have to be able to escape instead of crashing into an edge
+            config.backend = Backend::Sparse;
+
+            if config.layout.geometry().neighborhood != Neighborhood::Moore {
+                return Err(format!(
+                    "Census mode names objects by their apgcodes, which need the Moore neighborhood, not that of layout {}",
+                    config.layout
+                ));
+            }
+
+            if config.rule.states() > 2 {
+                return Err(format!(
+                    "Census mode steps objects on their own with only alive and dead cells, it can't use rule {} with dying states",
+                    config.rule
+                ));
+            }
+
+            if config.size == Size::Fit {
+                config.size = Size::Cells(16, 16);
+            }
+        }
+
+        if config.backend == Backend::Sparse {
+            if topology_given && config.topology != Topology::Plane {
+                return Err(format!(
+                    "The sparse backend is an unbounded plane, it can't use topology {}",
+                    config.topology
+                ));
+            }
+
+            // A pattern file's topology doesn't matter when there are no edges
+            config.topology = Topology::Plane;
+
+            if config.rule.birth()[0] {
+                return Err(format!(
+                    "Rule {} makes cells appear out of nothing, which would fill the whole plane of the sparse backend",
+                    config.rule
+                ));
+            }
+        }
+
+        if let Size::Cells(width, height) = config.size {
+            if !config.backend.fits(width, height, config.rule.states()) {
+                return Err(format!(
+                    "A {width}x{height} grid is too large for the {} backend to allocate, the sparse backend can take any size",
+                    config.backend
+                ));
+            }
+        }
+
+        let mode = if headless {
+            if config.size == Size::Fit {
+                return Err("Headless mode has no window to fit, it needs --size".to_string());
+            }
+
+            if config.reseed {
+                return Err("--reseed is only used in the window".to_string());
+            }
+
+            if hashlife && until_settled {
+                return Err(
+                    "--until-settled needs every generation, it can't be used with --hashlife"
+                        .to_string(),
+                );
+            }
+
+            if hashlife {
+                hashlife::check(
+                    &config.rule,
+                    config.topology,
+                    config.layout.geometry().neighborhood,
+                )?;
+
+                if generations.is_some_and(|generations| generations > hashlife::MAX_GENERATIONS) {
+                    return Err(format!(
+                        "HashLife can jump at most {} generations, any further and the coordinates of the cells would overflow",
+                        hashlife::MAX_GENERATIONS
+                    ));
+                }
+            }
+
+            if spaceships {
+                if config.layout.geometry().neighborhood != Neighborhood::Moore {
+                    return Err(format!(
+                        "--spaceships needs the Moore neighborhood, not that of layout {}",
+                        config.layout
+                    ));
+                }
+
+                if config.rule.states() > 2 {
+                    return Err(format!(
+                        "--spaceships steps objects with only alive and dead cells, it can't use rule {} with dying states",
+                        config.rule
+                    ));
+                }
+            }
+
+            Mode::Headless {
+                generations: generations.ok_or("Headless mode needs --generations")?,
+                save,
+                screenshot,
+                subpixel_view,
+                stats,
+                hashlife,
+                until_settled,
+                spaceships,
+            }
+        } else if census {
+            if config.reseed
+                || save.is_some()
+                || screenshot.is_some()
+                || subpixel_view.is_some()
+                || stats.is_some()
+                || hashlife
+                || until_settled
+                || spaceships
+            {
+                return Err(
+                    "Census mode only uses --generations and --soups besides the rule and soup options"
+                        .to_string(),
+                );
+            }
+
+            Mode::Census {
+                soups: soups.unwrap_or(1000),
+                generations: generations.unwrap_or(10_000),
+            }
+        } else {
+            if generations.is_some()
+                || save.is_some()
+                || screenshot.is_some()
+                || subpixel_view.is_some()
+                || stats.is_some()
+                || hashlife
+                || until_settled
+                || spaceships
+            {
+                return Err(
+                    "--generations, --save, --screenshot, --subpixel-view, --stats, --hashlife, --until-settled and --spaceships are only used in headless mode"
+                        .to_string(),
+                );
+            }
+
+            if soups.is_some() {
+                return Err("--soups is only used in census mode".to_string());
+            }
+
+            if let Size::Cells(width, height) = config.size {
+                window.size = Some(config.layout.geometry().pixel_size(width, height));
+            }
+
+            if calibrate {
+                Mode::Calibrate(window)
+            } else {
+                Mode::Window(window)
+            }
+        };
+
+        Ok(Some(Self { mode, config }))
+    }
+}
+
+fn parse<T: FromStr>(value: &str, name: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|err| format!("Invalid {name} \"{value}\": {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the arguments of a headless run, which unlike the window doesn't
+    /// read the saved settings.
+    fn headless(args: &str) -> Result<Options, String> {
+        let args = format!("headless --generations 10 {args}");
+        Options::parse(args.split_whitespace().map(str::to_string)).map(Option::unwrap)
+    }
+
+    #[test]
+    fn parses_sizes() {
+        let options = headless("--size 300x200").unwrap();
+        assert_eq!(options.config.size, Size::Cells(300, 200));
+
+        let options = headless("--size=64X48").unwrap();
+        assert_eq!(options.config.size, Size::Cells(64, 48));
+
+        for size in ["0x10", "10x0", "10", "10x-1", "axb", "10x10x10"] {
+            let err = headless(&format!("--size {size}")).err().unwrap();
+            assert!(
+                err.starts_with(&format!("Invalid size \"{size}\"")),
+                "{err}"
+            );
+        }
+
+        assert_eq!(
+            headless("").err().unwrap(),
+            "Headless mode has no window to fit, it needs --size"
+        );
+    }
+
+    #[test]
+    fn rejects_sizes_that_cant_be_allocated() {
+        let err = headless("--size 4294967295x4294967295 --backend bytes")
+            .err()
+            .unwrap();
+        assert!(err.contains("too large for the bytes backend"), "{err}");
+
+        let err = headless("--size 4294967295x4294967295 --rule 345/2/255")
+            .err()
+            .unwrap();
+        assert!(err.contains("too large for the packed backend"), "{err}");
+
+        let options = headless("--size 4294967295x4294967295 --backend sparse").unwrap();
+        assert_eq!(
+            options.config.size,
+            Size::Cells(u32::MAX, u32::MAX),
+            "the sparse backend only sets the area of the soup"
+        );
+    }
+
+    #[test]
+    fn parses_rules_with_topologies() {
+        let options = headless("--size 100x80 --rule B36/S23:T100,80").unwrap();
+        assert_eq!(options.config.rule, "B36/S23".parse().unwrap());
+        assert_eq!(options.config.topology, Topology::Torus);
+
+        let options = headless("--size 100x80 --rule B3/S23:K100*,80").unwrap();
+        assert_eq!(options.config.topology, Topology::KleinBottle);
+
+        let options = headless("--size 100x80 --rule 23/3").unwrap();
+        assert_eq!(options.config.rule, Rule::default());
+        assert_eq!(options.config.topology, Topology::Plane);
+
+        let err = headless("--size 100x80 --rule B3/S23:Q").err().unwrap();
+        assert!(err.starts_with("Invalid topology \"Q\""), "{err}");
+
+        let err = headless("--size 100x80 --rule B3/S23:T100,80x")
+            .err()
+            .unwrap();
+        assert!(err.starts_with("Invalid topology \"T100,80x\""), "{err}");
+
+        let err = headless("--size 100x80 --rule B9/S23").err().unwrap();
+        assert!(err.starts_with("Invalid rule \"B9/S23\""), "{err}");
+    }
+
+    #[test]
+    fn parses_offsets() {
+        let options = headless("--size 100x80 --offset 10,-20").unwrap();
+        assert_eq!(options.config.offset, Some((10, -20)));
+
+        let options = headless("--size 100x80 --offset=-3,4").unwrap();
+        assert_eq!(options.config.offset, Some((-3, 4)));
+
+        assert_eq!(
+            headless("--size 100x80 --offset 10").err().unwrap(),
+            "Invalid offset \"10\": expected X,Y"
+        );
+
+        let err = headless("--size 100x80 --offset 10,y").err().unwrap();
+        assert!(err.starts_with("Invalid offset \"y\""), "{err}");
+    }
+
+    #[test]
+    fn parses_run_generations() {
+        let parse = |args: &str| {
+            let args = format!("--layout rgb {args}");
+            match Options::parse(args.split_whitespace().map(str::to_string)) {
+                Ok(Some(Options {
+                    mode: Mode::Window(window),
+                    ..
+                })) => Ok(window.run_generations),
+                Ok(_) => panic!("{args} didn't open the window"),
+                Err(err) => Err(err),
+            }
+        };
+
+        assert_eq!(parse(""), Ok(100));
+        assert_eq!(parse("--run 1000"), Ok(1000));
+        assert_eq!(
+            parse("--run 0"),
+            Err("--run needs at least 1 generation".to_string())
+        );
+        assert!(parse("--run -5").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(
+            headless("--size 100x80 --frobnicate").err().unwrap(),
+            "Unknown argument \"--frobnicate\""
+        );
+        assert_eq!(
+            headless("--size 100x80 --seed").err().unwrap(),
+            "--seed needs a value"
+        );
+        assert_eq!(
+            headless("--size 100x80 --density 1.5").err().unwrap(),
+            "Invalid density 1.5: must be between 0 and 1"
+        );
+        assert_eq!(
+            headless("--size 100x80 --reseed").err().unwrap(),
+            "--reseed is only used in the window"
+        );
+
+        let err = headless("--size 100x80 --backend sparse --topology T")
+            .err()
+            .unwrap();
+        assert!(err.contains("can't use topology"), "{err}");
+    }
+}
@@ -0,0 +1,390 @@
+//! Turning the light each subpixel should give off into framebuffer values.
+//!
+//! Framebuffer values are not proportional to light: the monitor applies a
+//! transfer curve (usually sRGB) to them, and on many panels driving one
+//! channel also lights the neighboring emitters a little. Cells are summed in
+//! linear light, corrected for crosstalk and only then encoded, so a lone live
+//! cell lights its own subpixel at the intended brightness and nothing else.
+
+use std::{fmt, io, path::Path};
+
+/// The transfer curve between framebuffer values and light.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Transfer {
+    /// The piecewise sRGB curve most monitors follow.
+    #[default]
+    Srgb,
+    /// A pure power curve, light = value ^ gamma.
+    Gamma(f32),
+}
+
+impl Transfer {
+    /// Converts linear light between 0 and 1 into a framebuffer value between 0 and 1.
+    fn encode(self, light: f32) -> f32 {
+        match self {
+            Self::Srgb if light <= 0.0031308 => light * 12.92,
+            Self::Srgb => 1.055 * light.powf(1.0 / 2.4) - 0.055,
+            Self::Gamma(gamma) => light.powf(1.0 / gamma),
+        }
+    }
+}
+
+/// How a particular display turns framebuffer values into light, loaded from a
+/// calibration profile. The default is an ideal sRGB monitor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub transfer: Transfer,
+    /// The linear light a live cell should give off, between 0 and 1.
+    pub brightness: f32,
+    /// The light each emitter (rows: red, green, blue) gives off when each
+    /// channel (columns) is driven at full, relative to its own channel.
+    pub crosstalk: [[f32; 3]; 3],
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            transfer: Transfer::default(),
+            brightness: 1.0,
+            crosstalk: IDENTITY,
+        }
+    }
+}
+
+const IDENTITY: [[f32; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+#[derive(Debug)]
+pub enum ProfileError {
+    Io(io::Error),
+    InvalidLine { line: usize },
+    UnknownKey { line: usize, key: String },
+    InvalidValue { line: usize, key: String },
+    SingularCrosstalk,
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::InvalidLine { line } => write!(f, "expected KEY = VALUE on line {line}"),
+            Self::UnknownKey { line, key } => write!(
+                f,
+                "unknown key \"{key}\" on line {line}, expected gamma, brightness or crosstalk"
+            ),
+            Self::InvalidValue { line, key } => write!(f, "invalid {key} on line {line}"),
+            Self::SingularCrosstalk => write!(
+                f,
+                "the crosstalk matrix can't be inverted, some color can't be produced at all"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+impl From<io::Error> for ProfileError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> Result<Self, ProfileError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses a profile made of `KEY = VALUE` lines, with `#` starting a comment:
+    ///
+    /// ```text
+    /// gamma = 2.2         # or srgb
+    /// brightness = 0.8
+    /// crosstalk = 1 0.05 0, 0.03 1 0.02, 0 0.04 1
+    /// ```
+    ///
+    /// Anything left out keeps its default.
+    pub fn parse(text: &str) -> Result<Self, ProfileError> {
+        let mut profile = Self::default();
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line_number = line_index + 1;
+            let line = line.split('#').next().unwrap().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or(ProfileError::InvalidLine { line: line_number })?;
+            let (key, value) = (key.trim().to_ascii_lowercase(), value.trim());
+
+            let invalid = || ProfileError::InvalidValue {
+                line: line_number,
+                key: key.clone(),
+            };
+
+            match key.as_str() {
+                "gamma" => {
+                    profile.transfer = if value.eq_ignore_ascii_case("srgb") {
+                        Transfer::Srgb
+                    } else {
+                        let gamma: f32 = value.parse().map_err(|_| invalid())?;
+
+                        if !(gamma > 0.0 && gamma.is_finite()) {
+                            return Err(invalid());
+                        }
+
+                        Transfer::Gamma(gamma)
+                    };
+                }
+                "brightness" => {
+                    profile.brightness = value.parse().map_err(|_| invalid())?;
+
+                    if !(0.0..=1.0).contains(&profile.brightness) {
+                        return Err(invalid());
+                    }
+                }
+                "crosstalk" => {
+                    let rows: Vec<&str> = value.split(',').collect();
+
+                    if rows.len() != 3 {
+                        return Err(invalid());
+                    }
+
+                    for (row, text) in profile.crosstalk.iter_mut().zip(rows) {
+                        let numbers = text
+                            .split_whitespace()
+                            .map(str::parse::<f32>)
+                            .collect::<Result<Vec<_>, _>>()
+                            .map_err(|_| invalid())?;
+
+                        *row = numbers.try_into().map_err(|_| invalid())?;
+                    }
+
+                    if invert(&profile.crosstalk).is_none() {
+                        return Err(ProfileError::SingularCrosstalk);
+                    }
+                }
+                _ => {
+                    return Err(ProfileError::UnknownKey {
+                        line: line_number,
+                        key,
+                    })
+                }
+            }
+        }
+
+        Ok(profile)
+    }
+}
+
+/// Everything needed to encode pixels for a profile, computed once up front.
+pub struct Pipeline {
+    brightness: f32,
+    /// Maps the light wanted from each emitter to the channel values producing it.
+    correction: [[f32; 3]; 3],
+    /// Encoded values for `ENCODE_STEPS` evenly spaced light levels, since
+    /// evaluating the transfer curve for every pixel is too slow.
+    encoded: Vec<u8>,
+}
+
+const ENCODE_STEPS: usize = 4096;
+
+impl Pipeline {
+    pub fn new(profile: &Profile) -> Self {
+        let encoded = (0..ENCODE_STEPS)
+            .map(|step| {
+                let light = step as f32 / (ENCODE_STEPS - 1) as f32;
+                (profile.transfer.encode(light) * 255.0).round() as u8
+            })
+            .collect();
+
+        Self {
+            brightness: profile.brightness,
+            correction: invert(&profile.crosstalk).expect("profiles are checked when parsed"),
+            encoded,
+        }
+    }
+
+    /// Converts the light each emitter of a pixel should give off, with 1 for
+    /// a fully lit subpixel, into a 0RGB framebuffer value.
+    ///
+    /// Light that would need a negative drive to cancel crosstalk can't be
+    /// removed, so it is clamped.
+    pub fn pixel(&self, light: [f32; 3]) -> u32 {
+        let light = light.map(|light| light.min(1.0) * self.brightness);
+
+        let [red, green, blue] = self.correction.map(|row| {
+            let drive = row[0] * light[0] + row[1] * light[1] + row[2] * light[2];
+            let step = (drive.clamp(0.0, 1.0) * (ENCODE_STEPS - 1) as f32).round();
+            self.encoded[step as usize] as u32
+        });
+
+        0xFF000000 | (red << 16) | (green << 8) | blue
+    }
+}
+
+fn invert(m: &[[f32; 3]; 3]) -> Option<[[f32; 3]; 3]> {
+    let cofactor = |row: usize, column: usize| {
+        let (r0, r1) = ((row + 1) % 3, (row + 2) % 3);
+        let (c0, c1) = ((column + 1) % 3, (column + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+
+    let determinant = (0..3)
+        .map(|column| m[0][column] * cofactor(0, column))
+        .sum::<f32>();
+
+    if determinant.abs() < 1e-6 {
+        return None;
+    }
+
+    // The inverse is the transposed cofactor matrix over the determinant
+    Some(std::array::from_fn(|row| {
+        std::array::from_fn(|column| cofactor(column, row) / determinant)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The inverse of `Transfer::Srgb`, from a framebuffer value to light.
+    fn srgb_decode(value: f32) -> f32 {
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    fn channels(pixel: u32) -> [u32; 3] {
+        [(pixel >> 16) & 0xFF, (pixel >> 8) & 0xFF, pixel & 0xFF]
+    }
+
+    #[test]
+    fn parses_profiles() {
+        let profile = Profile::parse(
+            "# measured on the office monitor\n\
+             gamma = 2.2\n\
+             \n\
+             Brightness = 0.8   # dimmed\n\
+             crosstalk = 1 0.05 0, 0.03 1 0.02, 0 0.04 1\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            profile,
+            Profile {
+                transfer: Transfer::Gamma(2.2),
+                brightness: 0.8,
+                crosstalk: [[1.0, 0.05, 0.0], [0.03, 1.0, 0.02], [0.0, 0.04, 1.0]],
+            }
+        );
+
+        assert_eq!(Profile::parse("gamma = sRGB").unwrap(), Profile::default());
+        assert_eq!(Profile::parse("").unwrap(), Profile::default());
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for (text, expected_key) in [
+            ("gamma = 0", "gamma"),
+            ("gamma = -2.2", "gamma"),
+            ("gamma = inf", "gamma"),
+            ("gamma = NaN", "gamma"),
+            ("gamma = linear", "gamma"),
+            ("brightness = 1.5", "brightness"),
+            ("brightness = -0.1", "brightness"),
+            ("brightness = bright", "brightness"),
+            ("crosstalk = 1 0 0, 0 1 0", "crosstalk"),
+            ("crosstalk = 1 0, 0 1 0, 0 0 1", "crosstalk"),
+            ("crosstalk = 1 0 0 0, 0 1 0, 0 0 1", "crosstalk"),
+            ("crosstalk = 1 0 0, 0 one 0, 0 0 1", "crosstalk"),
+        ] {
+            let result = Profile::parse(&format!("# comment\n{text}\n"));
+            assert!(
+                matches!(&result, Err(ProfileError::InvalidValue { line: 2, key }) if key == expected_key),
+                "{text}: {result:?}"
+            );
+        }
+
+        assert!(matches!(
+            Profile::parse("gamma 2.2"),
+            Err(ProfileError::InvalidLine { line: 1 })
+        ));
+        assert!(matches!(
+            Profile::parse("gamma = 2.2\ncontrast = 1"),
+            Err(ProfileError::UnknownKey { line: 2, key }) if key == "contrast"
+        ));
+    }
+
+    #[test]
+    fn rejects_singular_crosstalk() {
+        for crosstalk in [
+            "1 1 0, 1 1 0, 0 0 1",
+            "0 0 0, 0 1 0, 0 0 1",
+            "1 0 1, 0 1 1, 1 1 2",
+        ] {
+            assert!(
+                matches!(
+                    Profile::parse(&format!("crosstalk = {crosstalk}")),
+                    Err(ProfileError::SingularCrosstalk)
+                ),
+                "{crosstalk}"
+            );
+        }
+    }
+
+    #[test]
+    fn srgb_round_trips() {
+        for value in 0..=255 {
+            let light = srgb_decode(value as f32 / 255.0);
+            let encoded = Transfer::Srgb.encode(light) * 255.0;
+            assert!((encoded - value as f32).abs() < 1e-3, "{value}: {encoded}");
+        }
+
+        // The lookup table is only off by one in the darkest steps, where the
+        // curve is steepest
+        let pipeline = Pipeline::new(&Profile::default());
+        for value in 0..=255 {
+            let light = srgb_decode(value as f32 / 255.0);
+            let [red, green, blue] = channels(pipeline.pixel([light, light, light]));
+            assert_eq!((red, green, blue), (red, red, red));
+            assert!(red.abs_diff(value) <= 1, "{value}: {red}");
+        }
+    }
+
+    #[test]
+    fn lone_cell_only_drives_its_own_channel() {
+        let profile = Profile::parse(
+            "gamma = 2.2\n\
+             brightness = 0.5\n\
+             crosstalk = 1 0.1 0.02, 0.08 1 0.1, 0.01 0.12 1",
+        )
+        .unwrap();
+        let pipeline = Pipeline::new(&profile);
+
+        for channel in 0..3 {
+            let mut light = [0.0; 3];
+            light[channel] = 1.0;
+
+            let values = channels(pipeline.pixel(light));
+            for (other, value) in values.into_iter().enumerate() {
+                assert_eq!(value > 0, other == channel, "{channel}: {values:?}");
+            }
+        }
+
+        // Driving the corrected values gives off the light that was asked for
+        let light = [0.6, 0.3, 0.8];
+        let drive = channels(pipeline.pixel(light)).map(|value| (value as f32 / 255.0).powf(2.2));
+        for (emitter, row) in profile.crosstalk.iter().enumerate() {
+            let given_off: f32 = row.iter().zip(drive).map(|(a, b)| a * b).sum();
+            let expected = light[emitter] * profile.brightness;
+            assert!(
+                (given_off - expected).abs() < 0.01,
+                "{emitter}: {given_off}"
+            );
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{HashMap, VecDeque};
+
+/// How many generations back a repeat is looked for, which is also the
+/// longest period that can be found.
+const HISTORY: usize = 4096;
+
+/// A grid that has started repeating itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    /// The first generation of the repeating part.
+    pub start: u64,
+    pub period: u64,
+}
+
+/// Spots when a whole grid has become periodic from the hashes of its recent
+/// generations, like a random soup that has settled into oscillating ash.
+pub struct CycleDetector {
+    /// The generation each hash in `recent` was last seen in.
+    seen: HashMap<u64, u64>,
+    /// The last `HISTORY` generations and their hashes, oldest first.
+    recent: VecDeque<(u64, u64)>,
+    /// How many generations in a row have to repeat with the same period.
+    confirmations: u64,
+    /// The period the latest generations repeated with, and how many of them
+    /// in a row did.
+    streak: (u64, u64),
+}
+
+impl CycleDetector {
+    /// A detector for hashes of the alive cells of a rule with `states`
+    /// states. The dying cells of Generations rules aren't hashed, but they are
+    /// what the alive cells of the last `states - 2` generations left behind,
+    /// so those have to repeat as well before the whole grid does.
+    pub fn new(states: u8) -> Self {
+        Self {
+            seen: HashMap::new(),
+            recent: VecDeque::new(),
+            confirmations: states as u64 - 1,
+            streak: (0, 0),
+        }
+    }
+
+    /// Records the hash of `generation`, which has to follow the last one
+    /// recorded, returning the cycle it completes if the same hash was seen in
+    /// one of the previous generations.
+    pub fn observe(&mut self, generation: u64, hash: u64) -> Option<Cycle> {
+        let previous = self.seen.insert(hash, generation);
+
+        self.recent.push_back((generation, hash));
+
+        if self.recent.len() > HISTORY {
+            let (oldest, hash) = self.recent.pop_front().unwrap();
+
+            if self.seen.get(&hash) == Some(&oldest) {
+                self.seen.remove(&hash);
+            }
+        }
+
+        let Some(start) = previous else {
+            self.streak = (0, 0);
+            return None;
+        };
+
+        let period = generation - start;
+
+        self.streak = match self.streak {
+            (streak_period, count) if streak_period == period => (period, count + 1),
+            _ => (period, 1),
+        };
+
+        (self.streak.1 >= self.confirmations).then_some(Cycle { start, period })
+    }
+
+    /// Forgets every generation, for when the grid was changed by hand.
+    pub fn clear(&mut self) {
+        self.seen.clear();
+        self.recent.clear();
+        self.streak = (0, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{Backend, Neighborhood};
+    use crate::rule::Rule;
+    use crate::stats::Stats;
+    use crate::topology::Topology;
+
+    const GLIDER: [(i64, i64); 5] = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+
+    /// Steps `cells` on a `width` by `height` grid for up to `generations`,
+    /// returning the first cycle found and the generation it was found in.
+    fn first_cycle(
+        rule: &str,
+        topology: Topology,
+        (width, height): (u32, u32),
+        cells: &[(i64, i64)],
+        generations: u64,
+    ) -> Option<(u64, Cycle)> {
+        let rule: Rule = rule.parse().unwrap();
+        let mut grid = Backend::Packed.create(width, height, rule.states());
+        let mut cycles = CycleDetector::new(rule.states());
+
+        for &(x, y) in cells {
+            grid.set(x, y, true);
+        }
+
+        if let Some(cycle) = cycles.observe(0, Stats::of_grid(grid.as_ref()).hash) {
+            return Some((0, cycle));
+        }
+
+        (1..=generations).find_map(|generation| {
+            let stats = grid.step(&rule, topology, Neighborhood::Moore);
+            cycles
+                .observe(generation, stats.hash)
+                .map(|cycle| (generation, cycle))
+        })
+    }
+
+    #[test]
+    fn still_life_has_period_one() {
+        let block = [(4, 4), (5, 4), (4, 5), (5, 5)];
+
+        assert_eq!(
+            first_cycle("B3/S23", Topology::Plane, (10, 10), &block, 10),
+            Some((
+                1,
+                Cycle {
+                    start: 0,
+                    period: 1
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn blinker_has_period_two() {
+        let blinker = [(3, 4), (4, 4), (5, 4)];
+
+        assert_eq!(
+            first_cycle("B3/S23", Topology::Plane, (10, 10), &blinker, 10),
+            Some((
+                2,
+                Cycle {
+                    start: 0,
+                    period: 2
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn glider_on_torus_has_period_four_times_size() {
+        assert_eq!(
+            first_cycle("B3/S23", Topology::Torus, (16, 16), &GLIDER, 100),
+            Some((
+                64,
+                Cycle {
+                    start: 0,
+                    period: 64
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn waits_for_dying_cells() {
+        // The lone cell is dying in generation 1 and gone in generation 2, so
+        // the empty grid only repeats from generation 2 on, although the alive
+        // cells of generations 1 and 2 are the same.
+        assert_eq!(
+            first_cycle("B2/S/C3", Topology::Plane, (10, 10), &[(4, 4)], 10),
+            Some((
+                3,
+                Cycle {
+                    start: 2,
+                    period: 1
+                }
+            ))
+        );
+
+        // Without dying cells the first repeat is enough
+        assert_eq!(
+            first_cycle("B2/S", Topology::Plane, (10, 10), &[(4, 4)], 10),
+            Some((
+                2,
+                Cycle {
+                    start: 1,
+                    period: 1
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn forgets_generations_older_than_history() {
+        // The glider moves one cell right and down every 4 generations, so it
+        // only comes back to where it started after 4 * 32 * 33 = 4224
+        // generations, which is further back than the detector looks.
+        let period = 4 * 32 * 33;
+        assert!(period > HISTORY as u64);
+
+        assert_eq!(
+            first_cycle("B3/S23", Topology::Torus, (32, 33), &GLIDER, period + 100),
+            None
+        );
+    }
+
+    #[test]
+    fn streak_breaks_on_new_hashes() {
+        let mut cycles = CycleDetector::new(3);
+
+        // Generation 2 repeats generation 1, but generation 3 is new
+        assert_eq!(cycles.observe(0, 10), None);
+        assert_eq!(cycles.observe(1, 11), None);
+        assert_eq!(cycles.observe(2, 11), None);
+        assert_eq!(cycles.observe(3, 12), None);
+        assert_eq!(cycles.observe(4, 12), None);
+        assert_eq!(
+            cycles.observe(5, 12),
+            Some(Cycle {
+                start: 4,
+                period: 1
+            })
+        );
+
+        cycles.clear();
+        assert_eq!(cycles.observe(6, 12), None);
+    }
+}
@@ -0,0 +1,67 @@
+//! The dying cells of Generations rules, in which a cell that dies passes
+//! through `states - 2` dying states before it is dead and can be born again.
+//!
+//! The packed backends keep them as bit planes next to the alive cells: the
+//! age of a dying cell, 1 right after it died and `states - 2` in its last
+//! dying state, is spread over `planes(states)` words, bit `i` of plane `p`
+//! holding bit `p` of the age of cell `i`. Age 0 means not dying.
+
+/// The number of bit planes the ages of dying cells take, 0 for rules without
+/// dying states.
+pub fn planes(states: u8) -> usize {
+    (u8::BITS - (states - 2).leading_zeros()) as usize
+}
+
+/// The cells of a word that are dying, whatever their age.
+pub fn dying(ages: &[u64]) -> u64 {
+    ages.iter().fold(0, |dying, plane| dying | plane)
+}
+
+/// Ages the dying cells of a word by one generation, letting those in the
+/// last dying state become dead, and makes the cells in `died` start dying.
+pub fn step(ages: &mut [u64], died: u64, states: u8) {
+    let last = (states - 2) as u64;
+
+    // The cells whose age matches `last` in every bit
+    let expiring = ages.iter().enumerate().fold(!0, |matches, (bit, plane)| {
+        let matching = if (last >> bit) & 1 == 1 {
+            *plane
+        } else {
+            !plane
+        };
+        matches & matching
+    });
+
+    // Add one to the age of every dying cell, carrying from plane to plane
+    let mut carry = dying(ages);
+
+    for plane in ages.iter_mut() {
+        let sum = *plane ^ carry;
+        carry &= *plane;
+        *plane = sum & !expiring;
+    }
+
+    // Cells that just died were alive, so their age was 0 and is now 1
+    if let Some(first) = ages.first_mut() {
+        *first |= died;
+    }
+}
+
+/// The state of cell `bit` of a word: 0 when dead, 1 when alive and from 2 up
+/// while dying.
+pub fn state(alive: u64, ages: &[u64], bit: u32) -> u8 {
+    if (alive >> bit) & 1 == 1 {
+        return 1;
+    }
+
+    let age = ages
+        .iter()
+        .enumerate()
+        .fold(0, |age, (plane, word)| age | (((word >> bit) & 1) << plane));
+
+    if age == 0 {
+        0
+    } else {
+        age as u8 + 1
+    }
+}
@@ -0,0 +1,389 @@
+use std::{fmt, str::FromStr};
+
+use rayon::{
+    iter::{
+        IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator,
+        ParallelIterator,
+    },
+    slice::ParallelSliceMut,
+};
+
+use crate::{
+    bitgrid::BitGrid,
+    decay,
+    rule::{neighbor_bit, Rule},
+    sparse::SparseGrid,
+    stats::Stats,
+    topology::Topology,
+};
+
+/// Storage for the cells of a `GameOfLife` and the kernel that steps them.
+pub trait Grid: Send + Sync {
+    /// Sets every cell to `f(x, y)`, leaving none dying. `f` is called in
+    /// parallel and in no particular order.
+    fn fill(&mut self, f: &(dyn Fn(u32, u32) -> bool + Sync));
+
+    /// Kills every cell.
+    fn clear(&mut self) {
+        self.fill(&|_, _| false);
+    }
+
+    /// Copies the cells of row `y` starting at `x = left` into `row`. Cells
+    /// outside the grid are dead.
+    fn copy_row(&self, y: i64, left: i64, row: &mut [bool]);
+
+    /// Like `copy_row`, but with the state of every cell: 0 when dead, 1 when
+    /// alive and from 2 up for the dying cells of Generations rules.
+    fn copy_states(&self, y: i64, left: i64, row: &mut [u8]);
+
+    /// The number of states a cell can be in, which is that of the rule the
+    /// grid was created for.
+    fn states(&self) -> u8;
+
+    /// A rectangle `(left, top, width, height)` containing every alive cell.
+    fn bounds(&self) -> (i64, i64, u64, u64);
+
+    /// Rectangles `(left, top, width, height)` that don't overlap and between
+    /// them contain every alive and dying cell, so that sparse grids can be
+    /// read without going over the empty space between their cells.
+    fn regions(&self) -> Vec<(i64, i64, u64, u64)> {
+        vec![self.bounds()]
+    }
+
+    /// Sets the cell at `(x, y)`, which is no longer dying either way. Cells
+    /// outside the grid are left alone.
+    fn set(&mut self, x: i64, y: i64, alive: bool);
+
+    /// The number of alive cells.
+    fn population(&self) -> u64;
+
+    /// Advances every cell by one generation, returning the stats of the new
+    /// one. `rule` has to have as many states as the grid was created with.
+    fn step(&mut self, rule: &Rule, topology: Topology, neighborhood: Neighborhood) -> Stats;
+}
+
+/// Which of the eight surrounding cells count as neighbors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Neighborhood {
+    /// All eight.
+    #[default]
+    Moore,
+    /// Six, for a hexagonal lattice stored with every odd row shifted half a
+    /// cell to the right. A cell on an even row doesn't see the cells to the
+    /// top right and bottom right of it, and one on an odd row doesn't see the
+    /// cells to the top left and bottom left.
+    Hexagonal,
+}
+
+impl Neighborhood {
+    /// Whether the cell `(dx, dy)` away from a cell on row `y` is one of its neighbors.
+    pub fn contains(self, dx: i32, dy: i32, y: u32) -> bool {
+        match self {
+            Self::Moore => dx != 0 || dy != 0,
+            Self::Hexagonal => {
+                let hidden = if y.is_multiple_of(2) { 1 } else { -1 };
+                (dx != 0 || dy != 0) && (dy == 0 || dx != hidden)
+            }
+        }
+    }
+}
+
+/// The columns of row `y` from `left` to `left + length` that are inside a
+/// `width` by `height` grid, or `None` if there are none.
+pub fn overlap(
+    y: i64,
+    left: i64,
+    length: usize,
+    width: u32,
+    height: u32,
+) -> Option<std::ops::Range<i64>> {
+    let columns = left.max(0)..(left + length as i64).min(width as i64);
+    ((0..height as i64).contains(&y) && !columns.is_empty()).then_some(columns)
+}
+
+/// The positions of every alive cell of a grid.
+pub fn alive_cells(grid: &dyn Grid) -> Vec<(i64, i64)> {
+    let mut cells = Vec::new();
+
+    for (left, top, width, height) in grid.regions() {
+        let mut row = vec![false; width as usize];
+
+        for y in top..top + height as i64 {
+            grid.copy_row(y, left, &mut row);
+
+            cells.extend(
+                row.iter()
+                    .enumerate()
+                    .filter(|(_, alive)| **alive)
+                    .map(|(x, _)| (left + x as i64, y)),
+            );
+        }
+    }
+
+    cells
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// One `bool` per cell, stepped one cell at a time.
+    Bytes,
+    /// 64 cells per `u64`, stepped a whole word at a time.
+    #[default]
+    Packed,
+    /// An unbounded plane of 64 by 64 tiles, only storing the ones with alive cells.
+    Sparse,
+}
+
+impl Backend {
+    /// A grid for a rule with `states` states.
+    pub fn create(self, width: u32, height: u32, states: u8) -> Box<dyn Grid> {
+        match self {
+            Self::Bytes => Box::new(ByteGrid::new(width, height, states)),
+            Self::Packed => Box::new(BitGrid::new(width, height, states)),
+            Self::Sparse => Box::new(SparseGrid::new(width, height, states)),
+        }
+    }
+
+    /// Whether `create` can allocate a grid this large, with none of its
+    /// buffers over the `isize::MAX` bytes that a `Vec` can hold. The sparse
+    /// backend only allocates the tiles it needs, so it takes any size.
+    pub fn fits(self, width: u32, height: u32, states: u8) -> bool {
+        let largest_buffer = match self {
+            Self::Bytes => Some(width as u64 * height as u64),
+            Self::Packed => (width as u64)
+                .div_ceil(64)
+                .checked_mul(height as u64 * 8 * decay::planes(states).max(1) as u64),
+            Self::Sparse => return true,
+        };
+
+        largest_buffer.is_some_and(|bytes| bytes <= isize::MAX as u64)
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bytes => write!(f, "bytes"),
+            Self::Packed => write!(f, "packed"),
+            Self::Sparse => write!(f, "sparse"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackendParseError(String);
+
+impl fmt::Display for BackendParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown backend \"{}\", expected \"bytes\", \"packed\" or \"sparse\"",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for BackendParseError {}
+
+impl FromStr for Backend {
+    type Err = BackendParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "bytes" | "scalar" => Ok(Self::Bytes),
+            "packed" | "bits" => Ok(Self::Packed),
+            "sparse" | "infinite" => Ok(Self::Sparse),
+            _ => Err(BackendParseError(s.to_string())),
+        }
+    }
+}
+
+/// The straightforward grid, one byte per cell.
+pub struct ByteGrid {
+    cells_current: Vec<bool>,
+    cells_next: Option<Vec<bool>>,
+    /// The state of every dying cell and 0 for the others.
+    dying_current: Vec<u8>,
+    dying_next: Vec<u8>,
+    width: u32,
+    height: u32,
+    states: u8,
+}
+
+impl ByteGrid {
+    pub fn new(width: u32, height: u32, states: u8) -> Self {
+        let cells = width as usize * height as usize;
+
+        Self {
+            cells_current: vec![false; cells],
+            cells_next: Some(vec![false; cells]),
+            dying_current: vec![0; cells],
+            dying_next: vec![0; cells],
+            width,
+            height,
+            states,
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        x as usize + y as usize * self.width as usize
+    }
+
+    /// The alive neighbors of a cell, one bit each as given by `neighbor_bit`.
+    fn alive_neighbors(
+        &self,
+        x: u32,
+        y: u32,
+        topology: Topology,
+        neighborhood: Neighborhood,
+    ) -> u8 {
+        let mut neighbors = 0;
+
+        // Cells away from the edges never need the topology to find their neighbors
+        let interior = x > 0 && y > 0 && x + 1 < self.width && y + 1 < self.height;
+
+        // Iterate through the 3x3 grid around the cell
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                // Skip the center cell and anything else outside the neighborhood
+                if !neighborhood.contains(dx, dy, y) {
+                    continue;
+                }
+
+                let neighbor = if interior {
+                    Some(((x as i32 + dx) as u32, (y as i32 + dy) as u32))
+                } else {
+                    topology.resolve(
+                        x as i64 + dx as i64,
+                        y as i64 + dy as i64,
+                        self.width,
+                        self.height,
+                    )
+                };
+
+                if let Some((nx, ny)) = neighbor {
+                    if self.cells_current[self.index(nx, ny)] {
+                        neighbors |= neighbor_bit(dx as i64, dy as i64);
+                    }
+                }
+            }
+        }
+
+        neighbors
+    }
+}
+
+impl Grid for ByteGrid {
+    fn fill(&mut self, f: &(dyn Fn(u32, u32) -> bool + Sync)) {
+        let width = self.width as usize;
+
+        self.cells_current
+            .par_iter_mut()
+            .enumerate()
+            .for_each(|(index, cell)| {
+                *cell = f((index % width) as u32, (index / width) as u32);
+            });
+
+        self.dying_current.fill(0);
+    }
+
+    fn copy_row(&self, y: i64, left: i64, row: &mut [bool]) {
+        row.fill(false);
+
+        let Some(columns) = overlap(y, left, row.len(), self.width, self.height) else {
+            return;
+        };
+
+        let start = self.index(0, y as u32);
+        row[(columns.start - left) as usize..(columns.end - left) as usize].copy_from_slice(
+            &self.cells_current[start + columns.start as usize..start + columns.end as usize],
+        );
+    }
+
+    fn copy_states(&self, y: i64, left: i64, row: &mut [u8]) {
+        row.fill(0);
+
+        let Some(columns) = overlap(y, left, row.len(), self.width, self.height) else {
+            return;
+        };
+
+        let start = self.index(0, y as u32);
+
+        for x in columns {
+            let index = start + x as usize;
+            row[(x - left) as usize] = if self.cells_current[index] {
+                1
+            } else {
+                self.dying_current[index]
+            };
+        }
+    }
+
+    fn states(&self) -> u8 {
+        self.states
+    }
+
+    fn bounds(&self) -> (i64, i64, u64, u64) {
+        (0, 0, self.width as u64, self.height as u64)
+    }
+
+    fn set(&mut self, x: i64, y: i64, alive: bool) {
+        if overlap(y, x, 1, self.width, self.height).is_some() {
+            let index = self.index(x as u32, y as u32);
+            self.cells_current[index] = alive;
+            self.dying_current[index] = 0;
+        }
+    }
+
+    fn population(&self) -> u64 {
+        self.cells_current.par_iter().filter(|cell| **cell).count() as u64
+    }
+
+    fn step(&mut self, rule: &Rule, topology: Topology, neighborhood: Neighborhood) -> Stats {
+        let width = self.width as usize;
+
+        let mut cells_next = self.cells_next.take().unwrap();
+        let mut dying_next = std::mem::take(&mut self.dying_next);
+
+        let stats = cells_next
+            .par_chunks_mut(width.max(1))
+            .zip(dying_next.par_chunks_mut(width.max(1)))
+            .enumerate()
+            .map(|(y, (row, dying_row))| {
+                for (x, (cell, dying_next)) in row.iter_mut().zip(dying_row).enumerate() {
+                    let alive_neighbors =
+                        self.alive_neighbors(x as u32, y as u32, topology, neighborhood);
+
+                    let alive = self.cells_current[x + y * width];
+                    let dying = self.dying_current[x + y * width];
+
+                    // Dying cells can't be born until they are dead
+                    *cell = dying == 0 && rule.next_state(alive, alive_neighbors);
+
+                    *dying_next = if alive && !*cell && self.states > 2 {
+                        2
+                    } else if dying != 0 && dying + 1 < self.states {
+                        dying + 1
+                    } else {
+                        0
+                    };
+                }
+
+                Stats::of_cells(
+                    y as i64,
+                    0,
+                    &self.cells_current[y * width..(y + 1) * width],
+                    row,
+                )
+            })
+            .reduce(Stats::default, Stats::merge);
+
+        self.cells_next = Some(cells_next);
+        std::mem::swap(&mut self.cells_current, self.cells_next.as_mut().unwrap());
+
+        self.dying_next = dying_next;
+        std::mem::swap(&mut self.dying_current, &mut self.dying_next);
+
+        stats
+    }
+}
@@ -0,0 +1,973 @@
+//! HashLife, which advances huge numbers of generations by remembering how
+//! every square it has seen before evolves.
+//!
+//! The universe is a quadtree in which equal squares are always the same node,
+//! so a repetitive pattern only needs a handful of nodes no matter how large it
+//! is. The result of stepping the center of a node is memoised, which turns
+//! regular patterns that would take billions of generations into a few
+//! thousand lookups. See <https://conwaylife.com/wiki/HashLife>.
+
+use std::collections::HashMap;
+
+use crate::{
+    grid::{Grid, Neighborhood},
+    pattern::PatternError,
+    rule::{neighbor_bit, Rule},
+    topology::Topology,
+};
+
+type NodeId = u32;
+
+/// A square of `2^level` by `2^level` cells.
+#[derive(Clone, Copy)]
+struct Node {
+    /// The quadrants, northwest, northeast, southwest and southeast. Unused
+    /// for the single cells at level 0.
+    children: [NodeId; 4],
+    level: u8,
+    population: u64,
+}
+
+const DEAD: NodeId = 0;
+const ALIVE: NodeId = 1;
+
+/// The number of nodes kept before unused ones are thrown away, about 64 bytes
+/// each including the tables.
+const MAX_NODES: usize = 1 << 23;
+
+/// The most generations `Universe::step` can be asked for. Patterns grow by at
+/// most a cell per generation and the root is kept a few times larger than
+/// them, which leaves every coordinate well inside an `i64`.
+pub const MAX_GENERATIONS: u64 = 1 << 56;
+
+/// Checks that a rule, topology and neighborhood can be run with HashLife.
+pub fn check(rule: &Rule, topology: Topology, neighborhood: Neighborhood) -> Result<(), String> {
+    if topology != Topology::Plane {
+        return Err(format!(
+            "HashLife runs on an unbounded plane, it can't use topology {topology}"
+        ));
+    }
+
+    if neighborhood != Neighborhood::Moore {
+        return Err("HashLife only supports the Moore neighborhood, not delta layouts".to_string());
+    }
+
+    if rule.states() > 2 {
+        return Err(format!(
+            "HashLife only has alive and dead cells, it can't run rule {rule} with dying states"
+        ));
+    }
+
+    if rule.birth()[0] {
+        return Err(format!(
+            "Rule {rule} makes cells appear out of nothing, which HashLife can't run"
+        ));
+    }
+
+    Ok(())
+}
+
+/// An unbounded plane stored as a canonical quadtree.
+pub struct Universe {
+    nodes: Vec<Node>,
+    /// The node for every combination of quadrants, so that equal squares are
+    /// always the same node.
+    canonical: HashMap<[NodeId; 4], NodeId>,
+    /// The center half of a node stepped `2^k` generations, by node and `k`.
+    results: HashMap<(NodeId, u8), NodeId>,
+    /// The empty node of every level.
+    empty: Vec<NodeId>,
+    root: NodeId,
+    /// The position of the top left cell of `root`.
+    origin: (i64, i64),
+    rule: Rule,
+    /// The number of nodes kept before unused ones are thrown away,
+    /// `MAX_NODES` unless a test needs to run out sooner.
+    max_nodes: usize,
+}
+
+impl Universe {
+    pub fn new(rule: Rule) -> Self {
+        let leaf = |population| Node {
+            children: [DEAD; 4],
+            level: 0,
+            population,
+        };
+
+        let mut universe = Self {
+            nodes: vec![leaf(0), leaf(1)],
+            canonical: HashMap::new(),
+            results: HashMap::new(),
+            empty: vec![DEAD],
+            root: DEAD,
+            origin: (0, 0),
+            rule,
+            max_nodes: MAX_NODES,
+        };
+
+        universe.root = universe.empty(3);
+        universe
+    }
+
+    pub fn population(&self) -> u64 {
+        self.nodes[self.root as usize].population
+    }
+
+    /// Replaces the universe with the cells of `grid`, keeping what has been
+    /// learned about how squares evolve.
+    pub fn load(&mut self, grid: &dyn Grid) {
+        let (left, top, ..) = grid.bounds();
+        let regions: Vec<_> = grid
+            .regions()
+            .into_iter()
+            .map(|(x, y, width, height)| (x - left, y - top, width, height))
+            .collect();
+        let mut row = [false; 8];
+
+        self.load_blocks(&regions, &mut |x, y| {
+            std::array::from_fn(|dy| {
+                grid.copy_row(top + y + dy as i64, left + x, &mut row);
+                to_bits(&row)
+            })
+        });
+
+        self.origin = (left, top);
+    }
+
+    /// Replaces the universe with the cells in `regions`, rectangles `(left,
+    /// top, width, height)` right and below of the origin. `block(x, y)` gives
+    /// the 8 by 8 cells from `(x, y)` on as rows of bits, bit `i` of row `j`
+    /// being the cell at `(x + i, y + j)`, and is only called for blocks that
+    /// overlap a region, so that nothing ever has to be expanded into a flat
+    /// list of cells and the empty space between regions costs nothing.
+    pub fn load_blocks(
+        &mut self,
+        regions: &[(i64, i64, u64, u64)],
+        block: &mut dyn FnMut(i64, i64) -> [u8; 8],
+    ) {
+        let extent = regions
+            .iter()
+            .map(|&(x, y, width, height)| (x + width as i64).max(y + height as i64))
+            .max()
+            .unwrap_or(0);
+
+        let mut level = 3;
+        while (1i64 << level) < extent {
+            level += 1;
+        }
+
+        self.root = self.build(regions, (0, 0), level, block);
+        self.origin = (0, 0);
+    }
+
+    fn build(
+        &mut self,
+        regions: &[(i64, i64, u64, u64)],
+        (x, y): (i64, i64),
+        level: u8,
+        block: &mut dyn FnMut(i64, i64) -> [u8; 8],
+    ) -> NodeId {
+        let size = 1 << level;
+        let overlaps = |&(left, top, width, height): &(i64, i64, u64, u64)| {
+            left < x + size && x < left + width as i64 && top < y + size && y < top + height as i64
+        };
+
+        if level == 3 {
+            return if regions.iter().any(overlaps) {
+                self.leaf(block(x, y))
+            } else {
+                self.empty(level)
+            };
+        }
+
+        // Only the regions in this square matter further down
+        let regions: Vec<_> = regions
+            .iter()
+            .filter(|region| overlaps(region))
+            .copied()
+            .collect();
+
+        if regions.is_empty() {
+            return self.empty(level);
+        }
+
+        let half = 1 << (level - 1);
+        let nw = self.build(&regions, (x, y), level - 1, block);
+        let ne = self.build(&regions, (x + half, y), level - 1, block);
+        let sw = self.build(&regions, (x, y + half), level - 1, block);
+        let se = self.build(&regions, (x + half, y + half), level - 1, block);
+        self.join([nw, ne, sw, se])
+    }
+
+    /// The 8 by 8 node with the cells in `rows`, bit `i` of row `j` being the
+    /// cell at `(i, j)`.
+    fn leaf(&mut self, rows: [u8; 8]) -> NodeId {
+        self.leaf_part(&rows, (0, 0), 3)
+    }
+
+    fn leaf_part(&mut self, rows: &[u8; 8], (x, y): (usize, usize), level: u8) -> NodeId {
+        if level == 0 {
+            return if (rows[y] >> x) & 1 != 0 { ALIVE } else { DEAD };
+        }
+
+        let half = 1 << (level - 1);
+        let nw = self.leaf_part(rows, (x, y), level - 1);
+        let ne = self.leaf_part(rows, (x + half, y), level - 1);
+        let sw = self.leaf_part(rows, (x, y + half), level - 1);
+        let se = self.leaf_part(rows, (x + half, y + half), level - 1);
+        self.join([nw, ne, sw, se])
+    }
+
+    /// Sets the bits of the alive cells of `node`, with its top left at `(x, y)`
+    /// in `rows`.
+    fn leaf_rows(&self, node: NodeId, (x, y): (usize, usize), rows: &mut [u8; 8]) {
+        let Node {
+            children,
+            level,
+            population,
+        } = self.nodes[node as usize];
+
+        if population == 0 {
+            return;
+        }
+
+        if level == 0 {
+            rows[y] |= 1 << x;
+            return;
+        }
+
+        let half = 1 << (level - 1);
+        self.leaf_rows(children[0], (x, y), rows);
+        self.leaf_rows(children[1], (x + half, y), rows);
+        self.leaf_rows(children[2], (x, y + half), rows);
+        self.leaf_rows(children[3], (x + half, y + half), rows);
+    }
+
+    /// Whether the cell at `(x, y)` is alive.
+    pub fn get(&self, x: i64, y: i64) -> bool {
+        let (mut x, mut y) = (x - self.origin.0, y - self.origin.1);
+        let mut node = self.nodes[self.root as usize];
+
+        let size = 1i64 << node.level;
+        if !(0..size).contains(&x) || !(0..size).contains(&y) {
+            return false;
+        }
+
+        while node.level > 0 && node.population > 0 {
+            let half = 1i64 << (node.level - 1);
+            let quadrant = (x >= half) as usize + 2 * (y >= half) as usize;
+
+            node = self.nodes[node.children[quadrant] as usize];
+            x %= half;
+            y %= half;
+        }
+
+        node.population > 0
+    }
+
+    /// A rectangle `(left, top, width, height)` just containing every alive
+    /// cell, all 0 when there are none.
+    pub fn bounds(&self) -> (i64, i64, u64, u64) {
+        if self.population() == 0 {
+            return (0, 0, 0, 0);
+        }
+
+        let (left, top, right, bottom) = self.extent(self.root, &mut HashMap::new());
+
+        (
+            self.origin.0 + left as i64,
+            self.origin.1 + top as i64,
+            right - left,
+            bottom - top,
+        )
+    }
+
+    /// The alive part of a node that isn't empty, as `(left, top, right,
+    /// bottom)` relative to its top left.
+    fn extent(
+        &self,
+        node: NodeId,
+        extents: &mut HashMap<NodeId, (u64, u64, u64, u64)>,
+    ) -> (u64, u64, u64, u64) {
+        if let Some(extent) = extents.get(&node) {
+            return *extent;
+        }
+
+        let Node {
+            children, level, ..
+        } = self.nodes[node as usize];
+
+        let extent = if level == 0 {
+            (0, 0, 1, 1)
+        } else {
+            let half = 1u64 << (level - 1);
+            let mut extent = (u64::MAX, u64::MAX, 0, 0);
+
+            for (quadrant, child) in children.into_iter().enumerate() {
+                if self.nodes[child as usize].population == 0 {
+                    continue;
+                }
+
+                let (x, y) = ((quadrant % 2) as u64 * half, (quadrant / 2) as u64 * half);
+                let (left, top, right, bottom) = self.extent(child, extents);
+
+                extent = (
+                    extent.0.min(x + left),
+                    extent.1.min(y + top),
+                    extent.2.max(x + right),
+                    extent.3.max(y + bottom),
+                );
+            }
+
+            extent
+        };
+
+        extents.insert(node, extent);
+        extent
+    }
+
+    /// Replaces the universe with the nodes of a macrocell file, given as the
+    /// lines after the header and comments along with their line numbers. The
+    /// top left of the last node, the root, ends up at the origin. See
+    /// <https://conwaylife.com/wiki/Macrocell>.
+    pub fn read_macrocell<'a>(
+        &mut self,
+        lines: impl IntoIterator<Item = (usize, &'a str)>,
+    ) -> Result<(), PatternError> {
+        // Nodes by their number in the file, counting from 1, and 0 for empty
+        let mut numbered = vec![DEAD];
+
+        for (line, text) in lines {
+            let invalid = || PatternError::InvalidNode { line };
+
+            let node = if text.starts_with(['.', '*', '$']) {
+                // An 8 by 8 leaf, with rows ending in `$` and trailing dead
+                // cells and rows left out
+                let mut rows = [0u8; 8];
+                let (mut x, mut y) = (0, 0);
+
+                for character in text.chars() {
+                    match character {
+                        '.' => x += 1,
+                        '*' if x < 8 && y < 8 => {
+                            rows[y] |= 1 << x;
+                            x += 1;
+                        }
+                        '*' => return Err(invalid()),
+                        '$' => {
+                            x = 0;
+                            y += 1;
+                        }
+                        character => {
+                            return Err(PatternError::UnexpectedCharacter { line, character })
+                        }
+                    }
+                }
+
+                self.leaf(rows)
+            } else {
+                // `LEVEL NW NE SW SE`, the children being earlier node numbers
+                let fields = text
+                    .split_whitespace()
+                    .map(|field| field.parse::<usize>().map_err(|_| invalid()))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let &[level, nw, ne, sw, se] = fields.as_slice() else {
+                    return Err(invalid());
+                };
+
+                if !(4..=62).contains(&level) {
+                    return Err(invalid());
+                }
+
+                let level = level as u8;
+                let mut children = [DEAD; 4];
+
+                for (child, number) in children.iter_mut().zip([nw, ne, sw, se]) {
+                    *child = match number {
+                        0 => self.empty(level - 1),
+                        number => *numbered.get(number).ok_or_else(invalid)?,
+                    };
+
+                    if self.nodes[*child as usize].level != level - 1 {
+                        return Err(invalid());
+                    }
+                }
+
+                self.join(children)
+            };
+
+            numbered.push(node);
+        }
+
+        self.root = match numbered.len() {
+            1 => self.empty(3),
+            _ => *numbered.last().unwrap(),
+        };
+        self.origin = (0, 0);
+
+        Ok(())
+    }
+
+    /// The nodes of the universe as the lines of a macrocell file, every node
+    /// after its children and the root last.
+    pub fn write_macrocell(&self) -> String {
+        let mut text = String::new();
+
+        if self.write_node(self.root, &mut HashMap::new(), &mut text) == 0 {
+            // An empty leaf for an empty universe
+            text.push_str("$\n");
+        }
+
+        text
+    }
+
+    /// Writes `node` unless it is empty or already written, returning its number.
+    fn write_node(
+        &self,
+        node: NodeId,
+        numbers: &mut HashMap<NodeId, usize>,
+        text: &mut String,
+    ) -> usize {
+        let Node {
+            children,
+            level,
+            population,
+        } = self.nodes[node as usize];
+
+        if population == 0 {
+            return 0;
+        }
+
+        if let Some(number) = numbers.get(&node) {
+            return *number;
+        }
+
+        if level == 3 {
+            let mut rows = [0u8; 8];
+            self.leaf_rows(node, (0, 0), &mut rows);
+
+            let last = rows.iter().rposition(|row| *row != 0).unwrap();
+            for row in &rows[..=last] {
+                let length = 8 - row.leading_zeros();
+                text.extend((0..length).map(|x| if (row >> x) & 1 != 0 { '*' } else { '.' }));
+                text.push('$');
+            }
+        } else {
+            let [nw, ne, sw, se] = children.map(|child| self.write_node(child, numbers, text));
+            text.push_str(&format!("{level} {nw} {ne} {sw} {se}"));
+        }
+
+        text.push('\n');
+
+        let number = numbers.len() + 1;
+        numbers.insert(node, number);
+        number
+    }
+
+    /// Clears `grid` and copies every alive cell into it. Cells outside a
+    /// bounded grid are lost.
+    pub fn store(&self, grid: &mut dyn Grid) {
+        grid.clear();
+        self.for_each_alive(&mut |x, y| grid.set(x, y, true));
+    }
+
+    /// Calls `f` with the position of every alive cell, going down only the
+    /// nodes that have any and reading whole 8 by 8 leaves at a time.
+    pub fn for_each_alive(&self, f: &mut dyn FnMut(i64, i64)) {
+        let mut stack = vec![(self.root, self.origin)];
+
+        while let Some((node, (x, y))) = stack.pop() {
+            let Node {
+                children,
+                level,
+                population,
+            } = self.nodes[node as usize];
+
+            if population == 0 {
+                continue;
+            }
+
+            if level <= 3 {
+                let mut rows = [0u8; 8];
+                self.leaf_rows(node, (0, 0), &mut rows);
+
+                for (dy, row) in rows.into_iter().enumerate() {
+                    for dx in (0..8).filter(|dx| (row >> dx) & 1 != 0) {
+                        f(x + dx, y + dy as i64);
+                    }
+                }
+                continue;
+            }
+
+            let half = 1 << (level - 1);
+            stack.extend([
+                (children[0], (x, y)),
+                (children[1], (x + half, y)),
+                (children[2], (x, y + half)),
+                (children[3], (x + half, y + half)),
+            ]);
+        }
+    }
+
+    /// Advances the universe by `generations`, one power of two at a time.
+    pub fn step(&mut self, generations: u64) {
+        assert!(generations <= MAX_GENERATIONS);
+
+        for k in 0..64 {
+            if generations & (1 << k) != 0 {
+                self.step_power_of_two(k);
+            }
+        }
+    }
+
+    fn step_power_of_two(&mut self, k: u8) {
+        // Give the pattern room to grow by `2^k` cells in every direction,
+        // since only the center half of the root is stepped
+        while self.level() < k + 2 || !self.is_centered() {
+            self.expand();
+        }
+        self.expand();
+
+        let level = self.level();
+
+        // When the nodes run out halfway the step is started over with only
+        // those of the universe left, and split in two if that isn't enough
+        let result = match self.result(self.root, k, self.max_nodes) {
+            Some(result) => result,
+            None => {
+                self.collect_garbage();
+
+                match self.result(self.root, k, self.max_nodes) {
+                    Some(result) => result,
+                    None if k > 0 => {
+                        self.collect_garbage();
+                        self.step_power_of_two(k - 1);
+                        self.step_power_of_two(k - 1);
+                        return;
+                    }
+                    // A single generation can't be split, it gets all the
+                    // nodes it needs
+                    None => self.result(self.root, k, usize::MAX).unwrap(),
+                }
+            }
+        };
+        self.root = result;
+
+        let quarter = 1i64 << (level - 2);
+        self.origin = (self.origin.0 + quarter, self.origin.1 + quarter);
+
+        self.shrink();
+
+        if self.nodes.len() > self.max_nodes {
+            self.collect_garbage();
+        }
+    }
+
+    fn level(&self) -> u8 {
+        self.nodes[self.root as usize].level
+    }
+
+    fn children(&self, node: NodeId) -> [NodeId; 4] {
+        self.nodes[node as usize].children
+    }
+
+    fn join(&mut self, children: [NodeId; 4]) -> NodeId {
+        if let Some(node) = self.canonical.get(&children) {
+            return *node;
+        }
+
+        let node = Node {
+            children,
+            level: self.nodes[children[0] as usize].level + 1,
+            population: children
+                .iter()
+                .map(|child| self.nodes[*child as usize].population)
+                .sum(),
+        };
+
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(node);
+        self.canonical.insert(children, id);
+        id
+    }
+
+    fn empty(&mut self, level: u8) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let smaller = *self.empty.last().unwrap();
+            let node = self.join([smaller; 4]);
+            self.empty.push(node);
+        }
+
+        self.empty[level as usize]
+    }
+
+    /// Whether everything alive is in the center half of the root.
+    fn is_centered(&self) -> bool {
+        let [nw, ne, sw, se] = self.children(self.root);
+        let [nw, ne, sw, se] = [
+            self.children(nw)[3],
+            self.children(ne)[2],
+            self.children(sw)[1],
+            self.children(se)[0],
+        ];
+
+        let inner: u64 = [nw, ne, sw, se]
+            .iter()
+            .map(|node| self.nodes[*node as usize].population)
+            .sum();
+
+        inner == self.population()
+    }
+
+    /// Doubles the size of the root, keeping it in the center.
+    fn expand(&mut self) {
+        let level = self.level();
+        let empty = self.empty(level - 1);
+        let [nw, ne, sw, se] = self.children(self.root);
+
+        let children = [
+            self.join([empty, empty, empty, nw]),
+            self.join([empty, empty, ne, empty]),
+            self.join([empty, sw, empty, empty]),
+            self.join([se, empty, empty, empty]),
+        ];
+        self.root = self.join(children);
+
+        let half = 1i64 << (level - 1);
+        self.origin = (self.origin.0 - half, self.origin.1 - half);
+    }
+
+    /// Halves the root as long as nothing alive is lost.
+    fn shrink(&mut self) {
+        while self.level() > 3 && self.is_centered() {
+            let level = self.level();
+            self.root = self.center(self.root);
+
+            let quarter = 1i64 << (level - 2);
+            self.origin = (self.origin.0 + quarter, self.origin.1 + quarter);
+        }
+    }
+
+    /// The center half of a node, without stepping it.
+    fn center(&mut self, node: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.children(node);
+        self.join([
+            self.children(nw)[3],
+            self.children(ne)[2],
+            self.children(sw)[1],
+            self.children(se)[0],
+        ])
+    }
+
+    /// The center half of `node` stepped `2^k` generations, where `k` is at
+    /// most the node's level minus 2, or `None` if that would take more than
+    /// `max_nodes` nodes.
+    fn result(&mut self, node: NodeId, k: u8, max_nodes: usize) -> Option<NodeId> {
+        if let Some(result) = self.results.get(&(node, k)) {
+            return Some(*result);
+        }
+
+        if self.nodes.len() > max_nodes {
+            return None;
+        }
+
+        let Node {
+            children,
+            level,
+            population,
+        } = self.nodes[node as usize];
+
+        let result = if population == 0 {
+            self.empty(level - 1)
+        } else if level == 2 {
+            self.step_base(node)
+        } else {
+            let [nw, ne, sw, se] = children;
+            let [_, nw_ne, nw_sw, nw_se] = self.children(nw);
+            let [ne_nw, _, ne_sw, ne_se] = self.children(ne);
+            let [sw_nw, sw_ne, _, sw_se] = self.children(sw);
+            let [se_nw, se_ne, se_sw, _] = self.children(se);
+
+            // Nine overlapping squares half the size of the node
+            let squares = [
+                nw,
+                self.join([nw_ne, ne_nw, nw_se, ne_sw]),
+                ne,
+                self.join([nw_sw, nw_se, sw_nw, sw_ne]),
+                self.join([nw_se, ne_sw, sw_ne, se_nw]),
+                self.join([ne_sw, ne_se, se_nw, se_ne]),
+                sw,
+                self.join([sw_ne, se_nw, sw_se, se_sw]),
+                se,
+            ];
+
+            // At full speed both halves of the way are stepped, otherwise only
+            // the second and the first just takes the centers
+            let full_speed = k == level - 2;
+
+            let mut inner = [DEAD; 9];
+            for (inner, square) in inner.iter_mut().zip(squares) {
+                *inner = if full_speed {
+                    self.result(square, level - 3, max_nodes)?
+                } else {
+                    self.center(square)
+                };
+            }
+
+            let [a, b, c, d, e, f, g, h, i] = inner;
+            let quadrants = [
+                self.join([a, b, d, e]),
+                self.join([b, c, e, f]),
+                self.join([d, e, g, h]),
+                self.join([e, f, h, i]),
+            ];
+
+            let k = if full_speed { level - 3 } else { k };
+            let mut stepped = [DEAD; 4];
+            for (stepped, quadrant) in stepped.iter_mut().zip(quadrants) {
+                *stepped = self.result(quadrant, k, max_nodes)?;
+            }
+
+            self.join(stepped)
+        };
+
+        self.results.insert((node, k), result);
+        Some(result)
+    }
+
+    /// The center 2 by 2 cells of a 4 by 4 node after one generation.
+    fn step_base(&mut self, node: NodeId) -> NodeId {
+        let mut cells = [[false; 4]; 4];
+
+        for (quadrant, child) in self.children(node).into_iter().enumerate() {
+            for (index, cell) in self.children(child).into_iter().enumerate() {
+                let x = (quadrant % 2) * 2 + index % 2;
+                let y = (quadrant / 2) * 2 + index / 2;
+                cells[y][x] = cell == ALIVE;
+            }
+        }
+
+        let next = |x: usize, y: usize| {
+            let mut neighbors = 0;
+
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let neighbor = cells[(y as i64 + dy) as usize][(x as i64 + dx) as usize];
+
+                    if (dx, dy) != (0, 0) && neighbor {
+                        neighbors |= neighbor_bit(dx, dy);
+                    }
+                }
+            }
+
+            if self.rule.next_state(cells[y][x], neighbors) {
+                ALIVE
+            } else {
+                DEAD
+            }
+        };
+
+        let children = [next(1, 1), next(2, 1), next(1, 2), next(2, 2)];
+        self.join(children)
+    }
+
+    /// Throws away every node that isn't part of the current universe, along
+    /// with all memoised results.
+    fn collect_garbage(&mut self) {
+        let mut old = Universe::new(self.rule);
+        std::mem::swap(self, &mut old);
+        self.max_nodes = old.max_nodes;
+
+        let mut copied = HashMap::from([(DEAD, DEAD), (ALIVE, ALIVE)]);
+        self.root = self.copy_from(&old, old.root, &mut copied);
+        self.origin = old.origin;
+    }
+
+    fn copy_from(
+        &mut self,
+        old: &Universe,
+        node: NodeId,
+        copied: &mut HashMap<NodeId, NodeId>,
+    ) -> NodeId {
+        if let Some(copy) = copied.get(&node) {
+            return *copy;
+        }
+
+        let children = old
+            .children(node)
+            .map(|child| self.copy_from(old, child, copied));
+        let copy = self.join(children);
+        copied.insert(node, copy);
+        copy
+    }
+}
+
+/// Packs 8 cells into a byte, the first in the lowest bit.
+fn to_bits(cells: &[bool; 8]) -> u8 {
+    cells
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (x, cell)| bits | ((*cell as u8) << x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bitgrid::BitGrid, life::splitmix64};
+
+    /// The size of the grids the universe is compared with.
+    const SIZE: u32 = 512;
+
+    /// A grid with a random 64 by 64 soup in the middle, far enough from the
+    /// edges that nothing reaches them in a few hundred generations.
+    fn soup() -> BitGrid {
+        let mut grid = BitGrid::new(SIZE, SIZE, 2);
+        grid.fill(&|x, y| {
+            (224..288).contains(&x)
+                && (224..288).contains(&y)
+                && splitmix64(x as u64 + y as u64 * SIZE as u64).is_multiple_of(3)
+        });
+        grid
+    }
+
+    fn assert_same(universe: &Universe, grid: &BitGrid, context: &str) {
+        let (left, top, width, height) = universe.bounds();
+        assert!(
+            left > 0
+                && top > 0
+                && left + width as i64 <= SIZE as i64
+                && top + height as i64 <= SIZE as i64,
+            "{context} reached the edges"
+        );
+
+        let mut stored = BitGrid::new(SIZE, SIZE, 2);
+        universe.store(&mut stored);
+
+        let mut expected = vec![false; SIZE as usize];
+        let mut actual = vec![false; SIZE as usize];
+
+        for y in 0..SIZE as i64 {
+            grid.copy_row(y, 0, &mut expected);
+            stored.copy_row(y, 0, &mut actual);
+            assert_eq!(actual, expected, "row {y} after {context}");
+        }
+
+        assert_eq!(universe.population(), grid.population(), "{context}");
+    }
+
+    /// Jumps `universe` and steps `grid` the same generations, checking that
+    /// they agree after every jump.
+    fn assert_jumps_like_grid(universe: &mut Universe, grid: &mut BitGrid, jumps: &[u64]) {
+        let rule = universe.rule;
+        let mut generation = 0;
+
+        for &jump in jumps {
+            universe.step(jump);
+
+            for _ in 0..jump {
+                grid.step(&rule, Topology::Plane, Neighborhood::Moore);
+            }
+
+            generation += jump;
+            assert_same(universe, grid, &format!("{rule}, generation {generation}"));
+        }
+    }
+
+    #[test]
+    fn jumps_like_stepping() {
+        for rule in ["B3/S23", "B36/S23", "B2-a/S12"] {
+            let mut grid = soup();
+            let mut universe = Universe::new(rule.parse().unwrap());
+            universe.load(&grid);
+            assert_same(&universe, &grid, rule);
+
+            assert_jumps_like_grid(&mut universe, &mut grid, &[1, 2, 3, 6, 16, 31, 64, 100]);
+        }
+    }
+
+    #[test]
+    fn jumps_like_stepping_when_nodes_run_out() {
+        // From a budget the jumps fit in after collecting garbage, through one
+        // where they have to be split, to one where single generations need
+        // more nodes than allowed
+        for max_nodes in [20_000, 5_000, 200] {
+            let mut grid = soup();
+            let mut universe = Universe::new(Rule::default());
+            universe.max_nodes = max_nodes;
+            universe.load(&grid);
+
+            assert_jumps_like_grid(&mut universe, &mut grid, &[64, 1, 127, 32]);
+
+            // Past the budget, only the nodes of the universe itself are kept
+            let kept = universe.nodes.len();
+            universe.collect_garbage();
+            assert!(kept <= max_nodes || kept == universe.nodes.len());
+        }
+    }
+
+    #[test]
+    fn macrocell_round_trips() {
+        let mut universe = Universe::new(Rule::default());
+        universe.load(&soup());
+        universe.step(50);
+
+        let text = universe.write_macrocell();
+        let mut loaded = Universe::new(Rule::default());
+        loaded
+            .read_macrocell(
+                text.lines()
+                    .enumerate()
+                    .map(|(index, line)| (index + 1, line)),
+            )
+            .unwrap();
+
+        assert_eq!(loaded.write_macrocell(), text);
+        assert_eq!(loaded.population(), universe.population());
+
+        // The root's top left ends up at the origin, so only the positions
+        // relative to the bounds are the same
+        let (left, top, width, height) = universe.bounds();
+        let (loaded_left, loaded_top, ..) = loaded.bounds();
+        assert_eq!((loaded.bounds().2, loaded.bounds().3), (width, height));
+
+        for y in 0..height as i64 {
+            for x in 0..width as i64 {
+                assert_eq!(
+                    loaded.get(loaded_left + x, loaded_top + y),
+                    universe.get(left + x, top + y)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn reads_golly_macrocell() {
+        // A glider in the top left and a block further down, as Golly writes them
+        let text = ".*$..*$***$\n4 1 0 0 0\n**$**$\n4 0 0 0 3\n5 2 0 0 4\n";
+        let mut universe = Universe::new(Rule::default());
+        universe
+            .read_macrocell(
+                text.lines()
+                    .enumerate()
+                    .map(|(index, line)| (index + 1, line)),
+            )
+            .unwrap();
+
+        assert_eq!(universe.population(), 9);
+        assert_eq!(universe.bounds(), (0, 0, 26, 26));
+        assert!(universe.get(1, 0) && universe.get(2, 1) && universe.get(0, 2));
+        assert!(universe.get(24, 24) && universe.get(25, 25));
+        assert!(!universe.get(0, 0) && !universe.get(23, 24));
+        assert_eq!(universe.write_macrocell(), text);
+    }
+
+    #[test]
+    fn bounds_wider_than_u32() {
+        let mut universe = Universe::new(Rule::default());
+        let regions = [(0, 0, 8, 8), (1 << 40, 1 << 35, 8, 8)];
+        universe.load_blocks(&regions, &mut |_, _| [1, 0, 0, 0, 0, 0, 0, 0]);
+
+        assert_eq!(universe.bounds(), (0, 0, (1 << 40) + 1, (1 << 35) + 1));
+    }
+}
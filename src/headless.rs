@@ -0,0 +1,153 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use crate::{
+    life::{Config, GameOfLife, Size},
+    pattern::Format,
+    screenshot,
+    stats::{self, Stats},
+};
+
+/// Files to write once the last generation has been reached.
+pub struct Outputs {
+    pub save: Option<PathBuf>,
+    pub screenshot: Option<PathBuf>,
+    pub subpixel_view: Option<PathBuf>,
+    /// The stats of every generation, as CSV or JSON.
+    pub stats: Option<PathBuf>,
+}
+
+/// Steps a random soup or pattern for `generations` generations without ever opening a
+/// window, printing the population before and after and writing `outputs`. With
+/// `hashlife` the generations are skipped in one jump instead of stepped one by one,
+/// and with `until_settled` stepping stops as soon as the grid is periodic. With
+/// `spaceships` the spaceships of the last generation are listed and outlined in the
+/// screenshots.
+pub fn run(
+    generations: u64,
+    hashlife: bool,
+    until_settled: bool,
+    spaceships: bool,
+    outputs: &Outputs,
+    config: &Config,
+) {
+    let Size::Cells(width, height) = config.size else {
+        unreachable!("headless mode always has a size");
+    };
+
+    let mut game = GameOfLife::new(width, height, config);
+    game.populate(config);
+
+    let start = match &config.pattern {
+        Some(pattern) => format!("{}x{} pattern", pattern.width, pattern.height),
+        None => format!("seed {}", game.seed),
+    };
+
+    println!(
+        "{}x{} cells, rule {}:{}, {start}",
+        game.width, game.height, config.rule, config.topology
+    );
+    println!("generation 0: population {}", game.grid.population());
+
+    // Only kept when it is going to be saved, it can get long
+    let mut series: Vec<(u64, Stats)> = Vec::new();
+    let mut record = |game: &GameOfLife| {
+        if outputs.stats.is_some() {
+            series.push((game.generation, game.stats));
+        }
+    };
+
+    record(&game);
+
+    let started = Instant::now();
+
+    if hashlife {
+        if let Err(err) = game.jump(generations) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+
+        record(&game);
+    } else {
+        for _ in 0..generations {
+            if until_settled && game.cycle.is_some() {
+                break;
+            }
+
+            game.step();
+            record(&game);
+        }
+    }
+
+    let elapsed = started.elapsed();
+    let stepped = game.generation;
+
+    println!(
+        "generation {stepped}: population {}",
+        game.grid.population()
+    );
+    println!(
+        "stepped {stepped} generations in {:.3}s ({:.1} generations/s)",
+        elapsed.as_secs_f64(),
+        stepped as f64 / elapsed.as_secs_f64()
+    );
+
+    match game.cycle {
+        Some(cycle) => println!(
+            "settled at generation {} with period {}",
+            cycle.start, cycle.period
+        ),
+        None if !hashlife => println!("not settled within {stepped} generations"),
+        None => {}
+    }
+
+    if spaceships {
+        if let Err(err) = game.set_highlight_spaceships(true) {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+
+        for spaceship in &game.spaceships {
+            println!("spaceship {spaceship}");
+        }
+
+        println!("{} spaceships", game.spaceships.len());
+    }
+
+    if let Some(path) = &outputs.stats {
+        finish_saving(path, stats::save_series(path, &series));
+    }
+
+    if let Some(path) = &outputs.save {
+        finish_saving(path, game.to_pattern(Format::of(path)).save(path));
+    }
+
+    if outputs.screenshot.is_some() || outputs.subpixel_view.is_some() {
+        let (width, height) = game.geometry.pixel_size(game.width, game.height);
+        let mut pixels = vec![0; width as usize * height as usize];
+        game.render(&mut pixels, width, height);
+
+        if let Some(path) = &outputs.screenshot {
+            finish_saving(path, screenshot::save_exact(path, &pixels, width, height));
+        }
+
+        if let Some(path) = &outputs.subpixel_view {
+            finish_saving(
+                path,
+                screenshot::save_subpixel_view(path, &pixels, width, height, &game.geometry),
+            );
+        }
+    }
+}
+
+fn finish_saving(path: &Path, result: Result<(), impl fmt::Display>) {
+    if let Err(err) = result {
+        eprintln!("Could not save {}: {err}", path.display());
+        std::process::exit(1);
+    }
+
+    println!("saved {}", path.display());
+}
@@ -0,0 +1,560 @@
+use std::{
+    fmt,
+    path::PathBuf,
+    str::FromStr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    color::{Pipeline, Profile},
+    cycle::{Cycle, CycleDetector},
+    grid::{alive_cells, Backend, Grid, Neighborhood},
+    hashlife::{self, Universe},
+    overlay,
+    pattern::{Format, Pattern},
+    rule::Rule,
+    spaceships::{Spaceship, SpaceshipFinder},
+    stats::Stats,
+    subpixel::{self, Geometry, Layout},
+    topology::Topology,
+    viewport::Viewport,
+};
+
+pub struct Config {
+    pub rule: Rule,
+    pub topology: Topology,
+    pub backend: Backend,
+    pub size: Size,
+    pub layout: Layout,
+    /// How the monitor turns framebuffer values into light.
+    pub profile: Profile,
+    /// The chance of each cell starting alive.
+    pub density: f64,
+    pub seed: Option<u64>,
+    /// A pattern to start from instead of a random soup.
+    pub pattern: Option<Pattern>,
+    /// Where the top left of `pattern` goes, `None` to center it.
+    pub offset: Option<(i64, i64)>,
+    /// Start a new random soup whenever the grid has settled.
+    pub reseed: bool,
+}
+
+/// The size of the grid in cells.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Size {
+    /// One cell for every subpixel of the window.
+    Fit,
+    Cells(u32, u32),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizeParseError(String);
+
+impl fmt::Display for SizeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "expected \"fit\" or WIDTHxHEIGHT with both at least 1, found \"{}\"",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for SizeParseError {}
+
+impl FromStr for Size {
+    type Err = SizeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("fit") {
+            return Ok(Self::Fit);
+        }
+
+        let error = || SizeParseError(s.to_string());
+
+        let (width, height) = s.split_once(['x', 'X']).ok_or_else(error)?;
+        let width = width.trim().parse::<u32>().map_err(|_| error())?;
+        let height = height.trim().parse::<u32>().map_err(|_| error())?;
+
+        if width == 0 || height == 0 {
+            return Err(error());
+        }
+
+        Ok(Self::Cells(width, height))
+    }
+}
+
+pub struct GameOfLife {
+    pub grid: Box<dyn Grid>,
+    pub width: u32,
+    pub height: u32,
+    pub rule: Rule,
+    pub topology: Topology,
+    density: f64,
+    /// Seed of the soup created by `randomize`, printed so that a run can be repeated.
+    pub seed: u64,
+    /// The number of generations stepped so far.
+    pub generation: u64,
+    pub geometry: Geometry,
+    /// The part of the grid that `render` draws.
+    pub viewport: Viewport,
+    /// The population, births, deaths and bounds of the current generation.
+    pub stats: Stats,
+    /// Whether `render` draws `stats` over the grid.
+    pub show_stats: bool,
+    cycles: CycleDetector,
+    /// The cycle the grid has settled into, once it has.
+    pub cycle: Option<Cycle>,
+    /// Whether `tick` starts a new soup some time after the grid has settled.
+    pub reseed: bool,
+    /// Whether `render` outlines `spaceships`.
+    pub highlight_spaceships: bool,
+    /// The spaceships in the current generation, kept up to date while they
+    /// are highlighted.
+    pub spaceships: Vec<Spaceship>,
+    spaceship_finder: SpaceshipFinder,
+    colors: Pipeline,
+    /// Kept between jumps so that what HashLife learned is reused.
+    hashlife: Option<Universe>,
+}
+
+/// The color spaceships are outlined in, magenta so that it stands out from
+/// the white of alive cells and the primary colors of single subpixels.
+const SPACESHIP_COLOR: u32 = 0xFF00FF;
+
+impl GameOfLife {
+    pub fn new(width: u32, height: u32, config: &Config) -> Self {
+        Self {
+            grid: config.backend.create(width, height, config.rule.states()),
+            width,
+            height,
+            rule: config.rule,
+            topology: config.topology,
+            density: config.density,
+            seed: config.seed.unwrap_or_else(rand::random),
+            generation: 0,
+            geometry: config.layout.geometry(),
+            viewport: Viewport::default(),
+            stats: Stats::default(),
+            show_stats: false,
+            cycles: CycleDetector::new(config.rule.states()),
+            cycle: None,
+            reseed: config.reseed,
+            highlight_spaceships: false,
+            spaceships: Vec::new(),
+            spaceship_finder: SpaceshipFinder::default(),
+            colors: Pipeline::new(&config.profile),
+            hashlife: None,
+        }
+    }
+
+    /// Starts from the configured pattern if there is one and a random soup otherwise.
+    pub fn populate(&mut self, config: &Config) {
+        match &config.pattern {
+            Some(pattern) => self.place(pattern, config.offset),
+            None => self.randomize(),
+        }
+
+        self.restart();
+    }
+
+    /// Starts over from generation 0 with a new random soup.
+    pub fn reseed(&mut self) {
+        self.seed = rand::random();
+        self.randomize();
+        self.restart();
+    }
+
+    /// Makes the current grid generation 0 and forgets its history.
+    fn restart(&mut self) {
+        self.generation = 0;
+        self.stats = Stats::of_grid(self.grid.as_ref());
+        self.cycles.clear();
+        self.cycle = self.cycles.observe(0, self.stats.hash);
+        self.spaceship_finder.clear();
+        self.update_spaceships();
+    }
+
+    /// Starts or stops outlining spaceships, searching the whole grid for them
+    /// when starting. Finding them steps every object on its own with the
+    /// Moore neighborhood and only alive and dead cells, so other
+    /// neighborhoods and Generations rules can't.
+    pub fn set_highlight_spaceships(&mut self, highlight: bool) -> Result<(), String> {
+        if highlight && self.geometry.neighborhood != Neighborhood::Moore {
+            return Err("spaceships can only be found with the Moore neighborhood".to_string());
+        }
+
+        if highlight && self.rule.states() > 2 {
+            return Err("spaceships can't be found with dying states".to_string());
+        }
+
+        self.highlight_spaceships = highlight;
+        self.spaceships.clear();
+
+        if highlight {
+            self.spaceships =
+                self.spaceship_finder
+                    .find(self.grid.as_ref(), &self.rule, self.generation);
+        }
+
+        Ok(())
+    }
+
+    /// Carries on searching for spaceships if they are highlighted, a few
+    /// tiles of the grid every generation, and moves the ones already found
+    /// along.
+    fn update_spaceships(&mut self) {
+        if self.highlight_spaceships {
+            self.spaceships =
+                self.spaceship_finder
+                    .search(self.grid.as_ref(), &self.rule, self.generation);
+        }
+    }
+
+    /// Clears the grid and puts `pattern` with its top left corner at `offset`,
+    /// or in the center of the grid if `offset` is `None`. Only the alive cells
+    /// are visited, so even huge macrocell patterns cost no more than their
+    /// population. Anything outside an edged grid is cut off, while the sparse
+    /// backend takes all of it whatever the size.
+    pub fn place(&mut self, pattern: &Pattern, offset: Option<(i64, i64)>) {
+        let (left, top) = offset.unwrap_or((
+            (self.width as i64 - pattern.width as i64) / 2,
+            (self.height as i64 - pattern.height as i64) / 2,
+        ));
+
+        self.grid.clear();
+
+        let grid = self.grid.as_mut();
+        pattern.for_each_alive(&mut |x, y| grid.set(left + x, top + y, true));
+    }
+
+    /// Sets the cell at `(x, y)`, ignoring cells outside the grid.
+    pub fn set_cell(&mut self, x: i64, y: i64, alive: bool) {
+        self.grid.set(x, y, alive);
+    }
+
+    /// Sets or clears the cells along the line from `from` to `to`, both
+    /// positions in the window in pixels. With `exact` only the cells behind
+    /// the subpixels under the line change, otherwise those of each pixel.
+    pub fn paint(&mut self, from: (f64, f64), to: (f64, f64), alive: bool, exact: bool) {
+        // The grid is no longer what the history remembers
+        self.cycles.clear();
+        self.cycle = None;
+
+        // The block of cells behind a single subpixel or pixel, or just one
+        // cell when zoomed in far enough for cells to cover whole pixels
+        let (brush_width, brush_height) = match self.viewport.pixels_per_cell() {
+            Some(_) => (1, 1),
+            None if exact => {
+                let block = self.viewport.cells_per_subpixel() as i64;
+                (block, block)
+            }
+            None => {
+                let (width, height) = self.viewport.scale(&self.geometry);
+                (width as i64, height as i64)
+            }
+        };
+
+        let (left, top) = self.viewport.origin(&self.geometry);
+        let from = self.viewport.cell_at(&self.geometry, from);
+        let to = self.viewport.cell_at(&self.geometry, to);
+
+        // Walk the line less than a brush at a time so that fast drags leave no gaps
+        let steps = ((to.0 - from.0).abs() / brush_width as f64)
+            .max((to.1 - from.1).abs() / brush_height as f64)
+            .ceil()
+            .max(1.0) as u32;
+
+        for step in 0..=steps {
+            let t = step as f64 / steps as f64;
+            let x = (from.0 + (to.0 - from.0) * t).floor() as i64;
+            let y = (from.1 + (to.1 - from.1) * t).floor() as i64;
+
+            // Line the brush up with the subpixels
+            let x = x - (x - left).rem_euclid(brush_width);
+            let y = y - (y - top).rem_euclid(brush_height);
+
+            for dy in 0..brush_height {
+                for dx in 0..brush_width {
+                    self.set_cell(x + dx, y + dy, alive);
+                }
+            }
+        }
+
+        self.update_spaceships();
+    }
+
+    /// Draws the grid into `pixels`, a `width` by `height` buffer of 0RGB
+    /// pixels, with each cell lighting one subpixel.
+    pub fn render(&self, pixels: &mut [u32], width: u32, height: u32) {
+        subpixel::render(
+            self.grid.as_ref(),
+            &self.geometry,
+            &self.viewport,
+            &self.colors,
+            pixels,
+            width,
+            height,
+        );
+
+        if self.highlight_spaceships {
+            // A cell or two of space around each, never less than a few pixels
+            let (scale_x, scale_y) = self.viewport.scale(&self.geometry);
+            let margin = (2.0 / scale_x.min(scale_y)).max(3.0);
+
+            for spaceship in &self.spaceships {
+                let (left, top, right, bottom) = spaceship.bounds;
+                let (left, top) = self
+                    .viewport
+                    .pixel_at(&self.geometry, (left as f64, top as f64));
+                let (right, bottom) = self
+                    .viewport
+                    .pixel_at(&self.geometry, (right as f64, bottom as f64));
+
+                overlay::draw_outline(
+                    pixels,
+                    width,
+                    height,
+                    (
+                        (left - margin).floor() as i64,
+                        (top - margin).floor() as i64,
+                        (right + margin).ceil() as i64,
+                        (bottom + margin).ceil() as i64,
+                    ),
+                    SPACESHIP_COLOR,
+                );
+            }
+        }
+
+        if self.show_stats {
+            overlay::draw_text(pixels, width, height, &self.describe_stats());
+        }
+    }
+
+    /// The lines of the stats overlay.
+    fn describe_stats(&self) -> Vec<String> {
+        let stats = &self.stats;
+
+        let bounds = match stats.bounds {
+            Some((left, top, ..)) => {
+                let (width, height) = stats.size();
+                format!("bounds {width}x{height} at {left},{top}")
+            }
+            None => "bounds none".to_string(),
+        };
+
+        let cycle = match self.cycle {
+            Some(Cycle { start, period }) => {
+                format!("settled at generation {start} with period {period}")
+            }
+            None => "not settled".to_string(),
+        };
+
+        let mut lines = vec![
+            format!("generation {}", self.generation),
+            format!("population {}", stats.population),
+            format!("births {}  deaths {}", stats.births, stats.deaths),
+            bounds,
+            cycle,
+        ];
+
+        if self.highlight_spaceships {
+            lines.push(format!("spaceships {}", self.spaceships.len()));
+        }
+
+        lines
+    }
+
+    /// The alive part of the grid as a pattern that can be saved in `format`.
+    /// Macrocell patterns are built as a quadtree straight from the grid, so
+    /// that even huge states never have to be listed cell by cell.
+    pub fn to_pattern(&self, format: Format) -> Pattern {
+        if format == Format::Macrocell {
+            let mut universe = Universe::new(self.rule);
+            universe.load(self.grid.as_ref());
+            return Pattern::from_universe(universe, Some(self.rule), Some(self.topology));
+        }
+
+        Pattern::from_cells(
+            &alive_cells(self.grid.as_ref()),
+            Some(self.rule),
+            Some(self.topology),
+        )
+    }
+
+    /// Saves the current generation next to earlier saves, in the format of
+    /// `extension`.
+    pub fn save(&self, extension: &str) {
+        let path = timestamped_path(&format!(".{extension}"));
+
+        match self.to_pattern(Format::of(&path)).save(&path) {
+            Ok(()) => println!("saved {}", path.display()),
+            Err(err) => eprintln!("could not save {}: {err}", path.display()),
+        }
+    }
+
+    /// Fills the grid with a random soup that only depends on the seed, the
+    /// density and the grid size.
+    pub fn randomize(&mut self) {
+        // A cell is alive if its hash falls below this fraction of the u64 range
+        let threshold = (self.density * u64::MAX as f64) as u64;
+        let seed = self.seed;
+        let width = self.width as u64;
+
+        self.grid
+            .fill(&|x, y| splitmix64(seed ^ splitmix64(x as u64 + y as u64 * width)) < threshold);
+    }
+
+    pub fn step(&mut self) {
+        self.stats = self
+            .grid
+            .step(&self.rule, self.topology, self.geometry.neighborhood);
+        self.generation += 1;
+
+        if self.cycle.is_none() {
+            self.cycle = self.cycles.observe(self.generation, self.stats.hash);
+        }
+
+        self.update_spaceships();
+    }
+
+    /// Advances `generations` generations at once with HashLife. It runs on
+    /// an unbounded plane, so with an edged backend anything that ends up
+    /// outside the grid is lost.
+    pub fn jump(&mut self, generations: u64) -> Result<(), String> {
+        hashlife::check(&self.rule, self.topology, self.geometry.neighborhood)?;
+
+        let universe = self
+            .hashlife
+            .get_or_insert_with(|| Universe::new(self.rule));
+
+        universe.load(self.grid.as_ref());
+        universe.step(generations);
+        universe.store(self.grid.as_mut());
+
+        // The universe knows its bounds without scanning, which could take
+        // forever on a sparse grid after a long jump. On edged grids it can
+        // be larger than what is left after cutting off the outside.
+        let (left, top, width, height) = universe.bounds();
+        let (grid_left, grid_top, grid_width, grid_height) = self.grid.bounds();
+        let population = self.grid.population();
+
+        // A jump isn't a single step, so there are no births and deaths, and
+        // the hash is left out since hashing needs a scan too
+        self.stats = Stats {
+            population,
+            bounds: (population > 0).then(|| {
+                (
+                    left.max(grid_left),
+                    top.max(grid_top),
+                    (left + width as i64).min(grid_left + grid_width as i64),
+                    (top + height as i64).min(grid_top + grid_height as i64),
+                )
+            }),
+            ..Stats::default()
+        };
+
+        self.generation += generations;
+        self.cycles.clear();
+        self.cycle = None;
+        self.spaceship_finder.clear();
+        self.update_spaceships();
+
+        Ok(())
+    }
+}
+
+/// A file name in the current directory that won't clash with earlier ones.
+pub fn timestamped_path(suffix: &str) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    PathBuf::from(format!("subpixel-life-{timestamp}{suffix}"))
+}
+
+/// The SplitMix64 finalizer, used as a counter-based rng so that every cell can
+/// be generated independently of the others and of the order rayon visits them.
+pub fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 100 by 100 game on `backend` starting from `pattern` at `offset`.
+    fn game(backend: Backend, pattern: Pattern, offset: (i64, i64)) -> GameOfLife {
+        let config = Config {
+            rule: Rule::default(),
+            topology: Topology::Plane,
+            backend,
+            size: Size::Cells(100, 100),
+            layout: Layout::default(),
+            profile: Profile::default(),
+            density: 0.5,
+            seed: Some(1),
+            pattern: Some(pattern),
+            offset: Some(offset),
+            reseed: false,
+        };
+
+        let mut game = GameOfLife::new(100, 100, &config);
+        game.populate(&config);
+        game
+    }
+
+    fn sorted(mut cells: Vec<(i64, i64)>) -> Vec<(i64, i64)> {
+        cells.sort_unstable();
+        cells
+    }
+
+    #[test]
+    fn places_only_alive_cells() {
+        // A soup larger than the grid, and the same as a quadtree
+        let cells: Vec<(i64, i64)> = (0..300 * 200)
+            .filter(|index| splitmix64(*index as u64).is_multiple_of(5))
+            .map(|index| (index % 300, index / 300))
+            .collect();
+        let pattern = || Pattern::from_cells(&cells, None, None);
+        let quadtree = || Pattern::parse_macrocell(&pattern().to_macrocell()).unwrap();
+
+        let offset = (-40, 30);
+        let placed = sorted(cells.iter().map(|(x, y)| (x - 40, y + 30)).collect());
+        let inside: Vec<_> = placed
+            .iter()
+            .copied()
+            .filter(|(x, y)| (0..100).contains(x) && (0..100).contains(y))
+            .collect();
+
+        for pattern in [pattern(), quadtree()] {
+            // The sparse backend takes all of it, whatever the size
+            let sparse = game(Backend::Sparse, pattern, offset);
+            assert_eq!(sorted(alive_cells(sparse.grid.as_ref())), placed);
+            assert_eq!(sparse.stats.population, placed.len() as u64);
+        }
+
+        for backend in [Backend::Packed, Backend::Bytes] {
+            for pattern in [pattern(), quadtree()] {
+                let edged = game(backend, pattern, offset);
+                assert_eq!(sorted(alive_cells(edged.grid.as_ref())), inside);
+            }
+        }
+    }
+
+    #[test]
+    fn places_far_apart_cells_on_sparse() {
+        let cells = [(0, 0), (1 << 40, 3), (5, 1 << 36)];
+        let pattern = Pattern::from_cells(&cells, None, None);
+        let game = game(Backend::Sparse, pattern, (-7, 9));
+
+        assert_eq!(
+            sorted(alive_cells(game.grid.as_ref())),
+            [(-7, 9), (-2, (1 << 36) + 9), ((1 << 40) - 7, 12)]
+        );
+    }
+}
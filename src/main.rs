@@ -1,162 +1,390 @@
+mod bitgrid;
+mod calibration;
+mod census;
+mod cli;
+mod color;
+mod cycle;
+mod decay;
+mod grid;
+mod hashlife;
+mod headless;
+mod life;
+mod overlay;
+mod pattern;
+mod png;
+mod rule;
+mod screenshot;
+mod settings;
+mod spaceships;
+mod sparse;
+mod stats;
+mod subpixel;
+mod topology;
+mod viewport;
+
 use std::{
     num::NonZeroU32,
     time::{Duration, Instant},
 };
 
-use rand::Rng;
-use rayon::iter::{IndexedParallelIterator, IntoParallelRefMutIterator, ParallelIterator};
+use calibration::Calibration;
+use cli::{Mode, Options, WindowOptions, USAGE};
+use life::{timestamped_path, Config, GameOfLife, Size};
+use subpixel::{Geometry, Layout};
+use viewport::Viewport;
 use winit::{
-    event::{Event, WindowEvent},
+    dpi::PhysicalSize,
+    event::{Event, MouseButton, MouseScrollDelta, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
     keyboard::{KeyCode, PhysicalKey},
     window::{Fullscreen, WindowBuilder},
 };
 
-struct GameOfLife {
-    cells_current: Vec<bool>,
-    cells_next: Option<Vec<bool>>,
-    width: u32,
-    height: u32,
-}
+/// How far the arrow keys move the view.
+const PAN_PIXELS: f64 = 100.0;
+
+/// The generations a settled grid is shown for before `--reseed` replaces it.
+const RESEED_DELAY: u64 = 200;
+
+/// The generations `J` skips ahead with HashLife.
+const JUMP_GENERATIONS: u64 = 1 << 16;
+
+impl App for GameOfLife {
+    type Config = Config;
+
+    fn new(width: u32, height: u32, config: &Config) -> Self {
+        let (width, height) = match config.size {
+            Size::Fit => config.layout.geometry().grid_size(width, height),
+            Size::Cells(width, height) => (width, height),
+        };
 
-impl GameOfLife {
-    fn new(width: u32, height: u32) -> Self {
-        Self {
-            cells_current: vec![false; (width * height) as usize],
-            cells_next: Some(vec![false; (width * height) as usize]),
-            width,
-            height,
+        let mut game = GameOfLife::new(width, height, config);
+        game.populate(config);
+
+        if config.pattern.is_none() {
+            println!("seed {}", game.seed);
         }
+
+        game
     }
 
-    // fn set_cell(&mut self, x: u32, y: u32, value: bool) {
-    //     self.cells_current[(x + y * self.width) as usize] = value;
-    // }
+    fn tick(&mut self) {
+        // let start = Instant::now();
 
-    fn index(&self, x: u32, y: u32) -> usize {
-        (x + y * self.width) as usize
-    }
+        self.step();
 
-    fn count_alive_neighbors(&self, x: u32, y: u32) -> u8 {
-        let mut count = 0;
+        // println!("{:?}", start.elapsed());
 
-        // Iterate through the 3x3 grid around the cell
-        for dy in -1..=1 {
-            for dx in -1..=1 {
-                // Skip the center cell
-                if dx == 0 && dy == 0 {
-                    continue;
-                }
+        let settled_for = self
+            .cycle
+            .map(|cycle| self.generation - cycle.start - cycle.period);
 
-                let nx = (x as i32 + dx) as u32;
-                let ny = (y as i32 + dy) as u32;
+        if self.reseed && settled_for.is_some_and(|generations| generations >= RESEED_DELAY) {
+            self.reseed();
+            println!("seed {}", self.seed);
+        }
+    }
 
-                // Check if the neighbor is alive and within bounds
-                if nx < self.width && ny < self.height && self.cells_current[self.index(nx, ny)] {
-                    count += 1;
+    fn key_released(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::KeyS => self.save("rle"),
+            KeyCode::KeyC => self.save("cells"),
+            KeyCode::KeyM => self.save("mc"),
+            KeyCode::KeyI => self.show_stats = !self.show_stats,
+            KeyCode::KeyH => match self.set_highlight_spaceships(!self.highlight_spaceships) {
+                Ok(()) => {
+                    for spaceship in &self.spaceships {
+                        println!("spaceship {spaceship}");
+                    }
                 }
-            }
+                Err(err) => eprintln!("could not find spaceships: {err}"),
+            },
+            KeyCode::KeyJ => match self.jump(JUMP_GENERATIONS) {
+                Ok(()) => println!("jumped to generation {}", self.generation),
+                Err(err) => eprintln!("could not jump: {err}"),
+            },
+            KeyCode::ArrowLeft => self.pan(PAN_PIXELS, 0.0),
+            KeyCode::ArrowRight => self.pan(-PAN_PIXELS, 0.0),
+            KeyCode::ArrowUp => self.pan(0.0, PAN_PIXELS),
+            KeyCode::ArrowDown => self.pan(0.0, -PAN_PIXELS),
+            KeyCode::Home => self.viewport = Viewport::default(),
+            _ => {}
         }
+    }
 
-        count
+    fn paint(&mut self, from: (f64, f64), to: (f64, f64), alive: bool, exact: bool) {
+        GameOfLife::paint(self, from, to, alive, exact);
     }
-}
 
-impl App for GameOfLife {
-    fn new(width: u32, height: u32) -> Self {
-        let mut game = GameOfLife::new(width * 3, height);
+    fn zoom(&mut self, position: (f64, f64), steps: i32) {
+        self.viewport.zoom_at(&self.geometry, position, steps);
+    }
 
-        game.cells_current.par_iter_mut().for_each(|cell| {
-            let mut rng = rand::thread_rng();
-            *cell = rng.gen_bool(0.5);
-        });
+    fn pan(&mut self, dx: f64, dy: f64) {
+        self.viewport.pan(&self.geometry, dx, dy);
+    }
 
-        game
+    fn draw(&self, pixels: &mut [u32], width: u32, height: u32) {
+        self.render(pixels, width, height);
     }
 
-    fn tick(&mut self) {
-        // let start = Instant::now();
+    fn geometry(&self) -> Geometry {
+        self.geometry.clone()
+    }
 
-        let width = self.width;
+    fn title(&self) -> String {
+        let settled = match self.cycle {
+            Some(cycle) => format!(", settled with period {}", cycle.period),
+            None => String::new(),
+        };
 
-        let mut cells_next = self.cells_next.take().unwrap();
+        format!(
+            "Subpixel Game of Life ({}:{}) - generation {}{settled}",
+            self.rule, self.topology, self.generation
+        )
+    }
+}
 
-        cells_next
-            .par_iter_mut()
-            .enumerate()
-            .for_each(|(index, cell)| {
-                let x = index as u32 % width;
-                let y = index as u32 / width;
+impl App for Calibration {
+    type Config = Config;
 
-                let alive_neighbors = self.count_alive_neighbors(x, y);
+    fn new(width: u32, height: u32, config: &Config) -> Self {
+        Calibration::new(width, height, config)
+    }
 
-                // Apply the rules of the Game of Life
-                *cell = match (self.cells_current[index], alive_neighbors) {
-                    (true, 2) | (true, 3) => true, // Stay alive
-                    (false, 3) => true,            // Become alive
-                    _ => false,                    // Otherwise, die
-                };
-            });
+    fn tick(&mut self) {
+        self.advance();
+    }
 
-        self.cells_next = Some(cells_next);
-        std::mem::swap(&mut self.cells_current, self.cells_next.as_mut().unwrap());
+    fn key_released(&mut self, keycode: KeyCode) {
+        match keycode {
+            KeyCode::ArrowLeft => self.cycle_layout(-1),
+            KeyCode::ArrowRight => self.cycle_layout(1),
+            KeyCode::ArrowUp => self.cycle_pattern(-1),
+            KeyCode::ArrowDown => self.cycle_pattern(1),
+            KeyCode::Enter => self.save(),
+            _ => {}
+        }
+    }
 
-        // println!("{:?}", start.elapsed());
+    fn draw(&self, pixels: &mut [u32], width: u32, height: u32) {
+        self.render(pixels, width, height);
     }
 
-    fn draw(&self, pixels: &mut [u32]) {
-        pixels
-            .par_iter_mut()
-            .enumerate()
-            .for_each(|(index, pixel)| {
-                let x = (index * 3) as u32 % self.width;
-                let y = (index * 3) as u32 / self.width;
+    fn geometry(&self) -> Geometry {
+        self.geometry.clone()
+    }
 
-                // TODO: I'm pretty sure this way of setting the color for each cell in this
-                // TODO: pixel is wrong. I believe I need to convert the rgb color in some
-                // TODO: way to ensure that the output of the subpixels is actually what I
-                // TODO: want.
+    fn title(&self) -> String {
+        self.title()
+    }
+}
 
-                let mut color = 0xFF000000;
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{USAGE}");
+            return;
+        }
+        Err(message) => {
+            eprintln!("{message}\n\nRun with --help to see all options.");
+            std::process::exit(2);
+        }
+    };
+
+    let config = options.config;
+
+    match options.mode {
+        Mode::Headless {
+            generations,
+            save,
+            screenshot,
+            subpixel_view,
+            stats,
+            hashlife,
+            until_settled,
+            spaceships,
+        } => headless::run(
+            generations,
+            hashlife,
+            until_settled,
+            spaceships,
+            &headless::Outputs {
+                save,
+                screenshot,
+                subpixel_view,
+                stats,
+            },
+            &config,
+        ),
+        Mode::Census { soups, generations } => census::run(soups, generations, &config),
+        Mode::Window(window) => run::<GameOfLife>(&window, config),
+        Mode::Calibrate(window) => run::<Calibration>(&window, config),
+    }
+}
 
-                if self.cells_current[self.index(x, y)] {
-                    color += 0xFF0000;
-                }
+trait App {
+    type Config;
 
-                if self.cells_current[self.index(x + 1, y)] {
-                    color += 0xFF00;
-                }
+    fn new(width: u32, height: u32, config: &Self::Config) -> Self;
+    fn tick(&mut self);
+    fn draw(&self, pixels: &mut [u32], width: u32, height: u32);
 
-                if self.cells_current[self.index(x + 2, y)] {
-                    color += 0xFF;
-                }
+    /// Called when a key that `run` doesn't handle itself is released.
+    fn key_released(&mut self, _keycode: KeyCode) {}
 
-                *pixel = color;
-            });
+    /// Called when the mouse is dragged from `from` to `to`, in pixels, with
+    /// the left button (`alive`) or the right button held. `exact` asks for
+    /// only the subpixel under the cursor instead of the whole pixel.
+    fn paint(&mut self, _from: (f64, f64), _to: (f64, f64), _alive: bool, _exact: bool) {}
+
+    /// Called when the mouse wheel is turned by `steps` notches over `position`.
+    fn zoom(&mut self, _position: (f64, f64), _steps: i32) {}
+
+    /// Called when the mouse is dragged by `(dx, dy)` pixels with the middle button held.
+    fn pan(&mut self, _dx: f64, _dy: f64) {}
+
+    /// The subpixel geometry `draw` assumes.
+    fn geometry(&self) -> Geometry {
+        Layout::default().geometry()
     }
+
+    /// The window title, followed by the speed.
+    fn title(&self) -> String;
 }
 
-fn main() {
-    run::<GameOfLife>("Subpixel Game of Life");
+/// How `run` spreads generations over frames.
+struct Pace {
+    /// Generations per second, whatever the frame rate.
+    speed: f64,
+    /// The frames per second `run` draws, that of the monitor.
+    frame_rate: f64,
+    /// Step as many generations as fit in a frame, ignoring `speed`.
+    fastest: bool,
+    paused: bool,
+    /// Generations left before pausing, when running a fixed number.
+    remaining: Option<u64>,
+    /// The generations `G` runs before pausing.
+    run_generations: u64,
+    /// The part of a generation that earlier frames were owed but didn't
+    /// step, for speeds that aren't a whole number of generations per frame.
+    owed: f64,
 }
 
-trait App {
-    fn new(width: u32, height: u32) -> Self;
-    fn tick(&mut self);
-    fn draw(&self, pixels: &mut [u32]);
+/// The slowest speed `-` goes down to, in generations per second.
+const MIN_SPEED: f64 = 1.0 / 64.0;
+
+/// The most generations a frame steps, however fast the speed.
+const MAX_GENERATIONS_PER_FRAME: f64 = 4096.0;
+
+impl Pace {
+    /// Handles the keys controlling the pace, returning whether `keycode` was one of them.
+    /// `N` only changes the pace here, stepping is up to the caller.
+    fn key_released(&mut self, keycode: KeyCode) -> bool {
+        match keycode {
+            KeyCode::Space => self.paused = !self.paused,
+            KeyCode::KeyN => self.paused = true,
+            KeyCode::Equal | KeyCode::NumpadAdd => self.set_speed(self.speed * 2.0),
+            KeyCode::Minus | KeyCode::NumpadSubtract => self.set_speed(self.speed / 2.0),
+            KeyCode::KeyF => self.fastest = !self.fastest,
+            KeyCode::KeyG => {
+                self.remaining = Some(self.run_generations);
+                self.paused = false;
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.clamp(MIN_SPEED, self.frame_rate * MAX_GENERATIONS_PER_FRAME);
+    }
+
+    /// How many generations the next frame should step.
+    fn generations_this_frame(&mut self) -> u64 {
+        self.owed += self.speed / self.frame_rate;
+
+        let generations = self.owed.floor();
+        self.owed -= generations;
+        generations as u64
+    }
+
+    /// Accounts for one generation, returning `false` and pausing instead if
+    /// a fixed number of generations has been run.
+    fn take_generation(&mut self) -> bool {
+        match &mut self.remaining {
+            Some(0) => {
+                self.remaining = None;
+                self.paused = true;
+                false
+            }
+            Some(remaining) => {
+                *remaining -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+
+    fn describe(&self) -> String {
+        let speed = if self.fastest {
+            "as fast as possible".to_string()
+        } else if self.speed >= 10.0 {
+            format!("{:.0} generations/s", self.speed)
+        } else {
+            format!("{:.2} generations/s", self.speed)
+        };
+
+        match (self.paused, self.remaining) {
+            (true, _) => format!("paused, {speed}"),
+            (false, Some(remaining)) => format!("{speed}, {remaining} generations left"),
+            (false, None) => speed,
+        }
+    }
 }
 
-fn run<T: App>(title: impl ToString) {
+fn run<T: App>(options: &WindowOptions, config: T::Config) {
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Wait);
 
-    let monitor = event_loop.primary_monitor().unwrap();
-
-    let window = WindowBuilder::new()
-        .with_title(title.to_string())
-        .with_inner_size(monitor.size())
-        .with_fullscreen(Some(Fullscreen::Borderless(None)))
-        .with_decorations(false)
+    let monitor = match options.monitor {
+        Some(index) => event_loop.available_monitors().nth(index),
+        None => event_loop
+            .primary_monitor()
+            .or_else(|| event_loop.available_monitors().next()),
+    };
+
+    let Some(monitor) = monitor else {
+        eprintln!("No monitor found, the available monitors are:");
+        for (index, monitor) in event_loop.available_monitors().enumerate() {
+            let name = monitor.name().unwrap_or_default();
+            eprintln!(
+                "    {index}: {name} {}x{}",
+                monitor.size().width,
+                monitor.size().height
+            );
+        }
+        std::process::exit(1);
+    };
+
+    let window = if options.fullscreen {
+        WindowBuilder::new()
+            .with_inner_size(monitor.size())
+            .with_fullscreen(Some(Fullscreen::Borderless(Some(monitor.clone()))))
+            .with_decorations(false)
+    } else {
+        WindowBuilder::new()
+            .with_inner_size(match options.size {
+                Some((width, height)) => PhysicalSize::new(width, height),
+                None => PhysicalSize::new(monitor.size().width / 2, monitor.size().height / 2),
+            })
+            .with_position(monitor.position())
+    };
+
+    let window = window
+        .with_title("Subpixel Game of Life")
         .with_resizable(false)
         .build(&event_loop)
         .unwrap();
@@ -165,24 +393,92 @@ fn run<T: App>(title: impl ToString) {
     let mut surface = softbuffer::Surface::new(&context, &window).unwrap();
 
     let size = window.inner_size();
-    let mut app = T::new(size.width, size.height);
+    let mut app = T::new(size.width, size.height, &config);
 
     let mut next_frame = Instant::now();
-    let refresh_rate = monitor.refresh_rate_millihertz().unwrap() as f32 / 1000.0;
-    let frame_time = Duration::from_secs_f32(1.0 / refresh_rate);
+    let frame_rate = monitor.refresh_rate_millihertz().unwrap_or(60_000) as f64 / 1000.0;
+    let frame_time = Duration::from_secs_f64(1.0 / frame_rate);
+
+    let mut pace = Pace {
+        speed: frame_rate,
+        frame_rate,
+        fastest: false,
+        paused: false,
+        remaining: None,
+        run_generations: options.run_generations,
+        owed: 0.0,
+    };
+
+    // One generation per frame unless asked for another speed
+    if let Some(speed) = options.speed {
+        pace.set_speed(speed);
+    }
 
-    let mut paused = false;
+    // Setting the title every frame is slow on some platforms
+    let mut title = String::new();
+    let mut title_updated = Instant::now();
+
+    // Where the cursor is, and whether the left (true) or right (false) button
+    // is painting
+    let mut cursor = None;
+    let mut painting = None;
+    let mut exact = false;
+    let mut panning = false;
+
+    // Touchpads scroll in pixels, which add up to notches of the wheel
+    let mut scrolled = 0.0;
 
     event_loop
         .run(|event, target| match event {
             Event::AboutToWait => {
-                if Instant::now() >= next_frame && !paused {
-                    next_frame += frame_time;
-                    app.tick();
+                let now = Instant::now();
+
+                if now >= next_frame && !pace.paused {
+                    if pace.fastest {
+                        // Step until the frame is used up, then draw once
+                        let deadline = now + frame_time;
+                        while pace.take_generation() {
+                            app.tick();
+
+                            if Instant::now() >= deadline {
+                                break;
+                            }
+                        }
+                        next_frame = Instant::now();
+                    } else {
+                        for _ in 0..pace.generations_this_frame() {
+                            if !pace.take_generation() {
+                                break;
+                            }
+                            app.tick();
+                        }
+
+                        // Don't try to catch up after falling far behind
+                        next_frame = (next_frame + frame_time).max(now - frame_time);
+                    }
+
                     window.request_redraw();
                 }
 
-                target.set_control_flow(ControlFlow::WaitUntil(next_frame));
+                if title_updated.elapsed() >= Duration::from_millis(250) {
+                    let status = format!("{} - {}", app.title(), pace.describe());
+
+                    if status != title {
+                        window.set_title(&status);
+                        title = status;
+                    }
+
+                    title_updated = Instant::now();
+                }
+
+                // Nothing changes while paused until an event arrives
+                target.set_control_flow(if pace.paused {
+                    ControlFlow::Wait
+                } else {
+                    ControlFlow::WaitUntil(
+                        next_frame.min(title_updated + Duration::from_millis(250)),
+                    )
+                });
             }
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::Resized(_) => {
@@ -200,7 +496,7 @@ fn run<T: App>(title: impl ToString) {
 
                     let mut surface = surface.buffer_mut().unwrap();
 
-                    app.draw(&mut surface);
+                    app.draw(&mut surface, size.width, size.height);
 
                     window.pre_present_notify();
                     surface.present().unwrap();
@@ -215,8 +511,98 @@ fn run<T: App>(title: impl ToString) {
                         target.exit();
                     }
 
-                    if keycode == KeyCode::Space && !event.state.is_pressed() {
-                        paused = !paused;
+                    if !event.state.is_pressed() && pace.key_released(keycode) {
+                        if keycode == KeyCode::KeyN && pace.paused {
+                            app.tick();
+                            window.request_redraw();
+                        }
+
+                        // Start counting frames from now instead of catching up
+                        next_frame = Instant::now();
+                        title_updated -= Duration::from_secs(1);
+                    } else if matches!(keycode, KeyCode::KeyP | KeyCode::KeyV)
+                        && !event.state.is_pressed()
+                    {
+                        let size = window.inner_size();
+                        let mut pixels = vec![0; size.width as usize * size.height as usize];
+                        app.draw(&mut pixels, size.width, size.height);
+
+                        let (path, result) = if keycode == KeyCode::KeyP {
+                            let path = timestamped_path(".png");
+                            let result =
+                                screenshot::save_exact(&path, &pixels, size.width, size.height);
+                            (path, result)
+                        } else {
+                            let path = timestamped_path("-subpixels.png");
+                            let result = screenshot::save_subpixel_view(
+                                &path,
+                                &pixels,
+                                size.width,
+                                size.height,
+                                &app.geometry(),
+                            );
+                            (path, result)
+                        };
+
+                        match result {
+                            Ok(()) => println!("saved {}", path.display()),
+                            Err(err) => eprintln!("could not save {}: {err}", path.display()),
+                        }
+                    } else if !event.state.is_pressed() {
+                        app.key_released(keycode);
+                        title_updated -= Duration::from_secs(1);
+                        window.request_redraw();
+                    }
+                }
+                WindowEvent::ModifiersChanged(modifiers) => {
+                    exact = modifiers.state().shift_key();
+                }
+                WindowEvent::MouseInput {
+                    state,
+                    button: MouseButton::Middle,
+                    ..
+                } => panning = state.is_pressed(),
+                WindowEvent::MouseInput { state, button, .. } => {
+                    painting = match (button, state.is_pressed()) {
+                        (MouseButton::Left, true) => Some(true),
+                        (MouseButton::Right, true) => Some(false),
+                        (MouseButton::Left | MouseButton::Right, false) => None,
+                        _ => painting,
+                    };
+
+                    if let (Some(alive), Some(position)) = (painting, cursor) {
+                        app.paint(position, position, alive, exact);
+                        window.request_redraw();
+                    }
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    let position = (position.x, position.y);
+
+                    if let (Some(alive), Some(from)) = (painting, cursor) {
+                        app.paint(from, position, alive, exact);
+                        window.request_redraw();
+                    }
+
+                    if let (true, Some(from)) = (panning, cursor) {
+                        app.pan(position.0 - from.0, position.1 - from.1);
+                        window.request_redraw();
+                    }
+
+                    cursor = Some(position);
+                }
+                WindowEvent::CursorLeft { .. } => cursor = None,
+                WindowEvent::MouseWheel { delta, .. } => {
+                    scrolled += match delta {
+                        MouseScrollDelta::LineDelta(_, lines) => lines as f64,
+                        MouseScrollDelta::PixelDelta(position) => position.y / 50.0,
+                    };
+
+                    let steps = scrolled.trunc();
+                    scrolled -= steps;
+
+                    if let (true, Some(position)) = (steps != 0.0, cursor) {
+                        app.zoom(position, steps as i32);
+                        window.request_redraw();
                     }
                 }
                 WindowEvent::CloseRequested => target.exit(),
@@ -226,3 +612,4 @@ fn run<T: App>(title: impl ToString) {
         })
         .unwrap();
 }
+
@@ -0,0 +1,126 @@
+/// The size of every font pixel in screen pixels.
+const SCALE: u32 = 3;
+
+/// The width and height of a glyph in font pixels, not counting the gap
+/// after it.
+const GLYPH: (u32, u32) = (3, 5);
+
+/// Draws lines of text over the top left corner of `pixels`, a `width` by
+/// `height` buffer of 0RGB pixels, in white on a black box.
+pub fn draw_text(pixels: &mut [u32], width: u32, height: u32, lines: &[String]) {
+    let columns = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0) as u32;
+
+    let advance = (GLYPH.0 + 1) * SCALE;
+    let line_height = (GLYPH.1 + 2) * SCALE;
+
+    // A margin of one font pixel around the text
+    let box_width = (columns * advance + SCALE).min(width);
+    let box_height = (lines.len() as u32 * line_height).min(height);
+
+    for y in 0..box_height {
+        let start = (y * width) as usize;
+        pixels[start..start + box_width as usize].fill(0);
+    }
+
+    for (row, line) in lines.iter().enumerate() {
+        let top = row as u32 * line_height + SCALE;
+
+        for (column, character) in line.chars().enumerate() {
+            let left = column as u32 * advance + SCALE;
+
+            for (glyph_y, bits) in glyph(character).into_iter().enumerate() {
+                for glyph_x in 0..GLYPH.0 {
+                    if (bits >> (GLYPH.0 - 1 - glyph_x)) & 1 == 0 {
+                        continue;
+                    }
+
+                    for dy in 0..SCALE {
+                        for dx in 0..SCALE {
+                            let x = left + glyph_x * SCALE + dx;
+                            let y = top + glyph_y as u32 * SCALE + dy;
+
+                            if x < width && y < height {
+                                pixels[(x + y * width) as usize] = 0xFFFFFF;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws the outline of the rectangle from `(left, top)` to just before
+/// `(right, bottom)` in `color`, as much of it as is inside `pixels`.
+pub fn draw_outline(
+    pixels: &mut [u32],
+    width: u32,
+    height: u32,
+    (left, top, right, bottom): (i64, i64, i64, i64),
+    color: u32,
+) {
+    for y in top.max(0)..bottom.min(height as i64) {
+        for x in left.max(0)..right.min(width as i64) {
+            if x == left || x == right - 1 || y == top || y == bottom - 1 {
+                pixels[(x + y * width as i64) as usize] = color;
+            }
+        }
+    }
+}
+
+/// The rows of a 3 by 5 glyph, the leftmost font pixel in the highest bit.
+/// Lowercase letters are drawn as uppercase.
+fn glyph(character: char) -> [u8; 5] {
+    match character.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ' ' => [0; 5],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        _ => [0b111, 0b001, 0b010, 0b000, 0b010],
+    }
+}
@@ -0,0 +1,794 @@
+use std::{collections::HashSet, fmt, io, path::Path};
+
+use crate::{
+    hashlife::Universe,
+    rule::{Rule, RuleParseError},
+    topology::{Topology, TopologyParseError},
+};
+
+/// A rectangle of cells loaded from a pattern file.
+pub struct Pattern {
+    pub width: u64,
+    pub height: u64,
+    cells: Cells,
+    /// The rule the file asks for, if it names one.
+    pub rule: Option<Rule>,
+    /// The topology appended to the rule, if any. Golly's grid sizes after the
+    /// topology letter are ignored, the grid size is set separately.
+    pub topology: Option<Topology>,
+}
+
+/// The most cells a pattern file other than macrocell can be wide or tall.
+const MAX_SIZE: u64 = 1 << 30;
+
+/// The most alive cells a pattern file other than macrocell can have, so that
+/// a run like `4000000000o` can't take all the memory.
+const MAX_ALIVE: usize = 1 << 26;
+
+/// The cells of a pattern.
+enum Cells {
+    /// The positions of the alive cells, sorted by row and then by column.
+    Alive(Vec<(u32, u32)>),
+    /// A universe too large to list cell by cell, with the pattern's top left
+    /// at `(left, top)` in it.
+    Quadtree {
+        universe: Box<Universe>,
+        left: i64,
+        top: i64,
+    },
+}
+
+/// The pattern file formats, told apart by their extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Golly's run length encoded format, for anything not recognised.
+    Rle,
+    /// `.cells` files.
+    Plaintext,
+    /// `.mc` files, Golly's quadtree format for very large patterns.
+    Macrocell,
+}
+
+impl Format {
+    pub fn of(path: &Path) -> Self {
+        let extension = path.extension().unwrap_or_default();
+
+        if extension.eq_ignore_ascii_case("cells") {
+            Self::Plaintext
+        } else if extension.eq_ignore_ascii_case("mc") {
+            Self::Macrocell
+        } else {
+            Self::Rle
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum PatternError {
+    Io(io::Error),
+    InvalidHeader(String),
+    InvalidRule(RuleParseError),
+    InvalidTopology(TopologyParseError),
+    UnexpectedCharacter {
+        line: usize,
+        character: char,
+    },
+    RunTooLong {
+        line: usize,
+    },
+    /// The pattern grows past `MAX_SIZE` or `MAX_ALIVE` on this line.
+    TooLarge {
+        line: usize,
+    },
+    /// A cell in one of the dying states of a Generations rule, which can't be
+    /// loaded as only alive and dead cells are.
+    DyingState {
+        line: usize,
+        character: char,
+    },
+    InvalidNode {
+        line: usize,
+    },
+    /// The pattern is wider or taller than `MAX_SIZE`, which only macrocell
+    /// files can be.
+    TooLargeToWrite {
+        width: u64,
+        height: u64,
+    },
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::InvalidHeader(header) => write!(
+                f,
+                "invalid header \"{header}\", expected \"x = WIDTH, y = HEIGHT, rule = RULE\""
+            ),
+            Self::InvalidRule(err) => write!(f, "invalid rule: {err}"),
+            Self::InvalidTopology(err) => write!(f, "{err}"),
+            Self::UnexpectedCharacter { line, character } => {
+                write!(f, "unexpected character '{character}' on line {line}")
+            }
+            Self::RunTooLong { line } => write!(f, "run count on line {line} is too large"),
+            Self::TooLarge { line } => write!(
+                f,
+                "the pattern is too large on line {line}, patterns this large have to be macrocell files"
+            ),
+            Self::DyingState { line, character } => write!(
+                f,
+                "'{character}' on line {line} is a dying cell of a Generations rule, only alive and dead cells can be loaded"
+            ),
+            Self::InvalidNode { line } => write!(f, "invalid macrocell node on line {line}"),
+            Self::TooLargeToWrite { width, height } => write!(
+                f,
+                "the pattern is {width}x{height}, patterns more than {MAX_SIZE} cells wide or tall can only be saved as macrocell .mc files"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl From<io::Error> for PatternError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl Pattern {
+    /// Cuts the bounding box of the alive cells at `cells` out of the plane.
+    /// Boxes larger than `MAX_SIZE` are kept as a quadtree, like macrocell
+    /// patterns.
+    pub fn from_cells(
+        cells: &[(i64, i64)],
+        rule: Option<Rule>,
+        topology: Option<Topology>,
+    ) -> Self {
+        let left = cells.iter().map(|(x, _)| *x).min().unwrap_or(0);
+        let top = cells.iter().map(|(_, y)| *y).min().unwrap_or(0);
+        let right = cells.iter().map(|(x, _)| *x).max().unwrap_or(-1);
+        let bottom = cells.iter().map(|(_, y)| *y).max().unwrap_or(-1);
+
+        if right - left < MAX_SIZE as i64 && bottom - top < MAX_SIZE as i64 {
+            let alive = cells
+                .iter()
+                .map(|(x, y)| ((x - left) as u32, (y - top) as u32))
+                .collect();
+
+            return Self::from_alive(alive, (0, 0), rule, topology);
+        }
+
+        let alive: HashSet<(i64, i64)> = cells.iter().map(|(x, y)| (x - left, y - top)).collect();
+
+        // Only the blocks with alive cells in them, however far apart
+        let mut blocks: Vec<_> = alive
+            .iter()
+            .map(|&(x, y)| (x / 8 * 8, y / 8 * 8, 8, 8))
+            .collect();
+        blocks.sort_unstable();
+        blocks.dedup();
+
+        // Only stored, so the rule doesn't matter
+        let mut universe = Universe::new(Rule::default());
+        universe.load_blocks(&blocks, &mut |x, y| {
+            std::array::from_fn(|dy| {
+                (0..8).fold(0, |bits, dx| {
+                    bits | ((alive.contains(&(x + dx, y + dy as i64)) as u8) << dx)
+                })
+            })
+        });
+
+        Self {
+            width: (right - left + 1) as u64,
+            height: (bottom - top + 1) as u64,
+            cells: Cells::Quadtree {
+                universe: Box::new(universe),
+                left: 0,
+                top: 0,
+            },
+            rule,
+            topology,
+        }
+    }
+
+    /// Cuts the bounding box of the alive cells out of a universe, without
+    /// ever listing them one by one.
+    pub fn from_universe(
+        universe: Universe,
+        rule: Option<Rule>,
+        topology: Option<Topology>,
+    ) -> Self {
+        let (left, top, width, height) = universe.bounds();
+
+        Self {
+            width,
+            height,
+            cells: Cells::Quadtree {
+                universe: Box::new(universe),
+                left,
+                top,
+            },
+            rule,
+            topology,
+        }
+    }
+
+    /// Loads a pattern in the format its extension says.
+    pub fn load(path: &Path) -> Result<Self, PatternError> {
+        let text = std::fs::read_to_string(path)?;
+
+        match Format::of(path) {
+            Format::Rle => Self::parse_rle(&text),
+            Format::Plaintext => Self::parse_plaintext(&text),
+            Format::Macrocell => Self::parse_macrocell(&text),
+        }
+    }
+
+    /// Saves a pattern in the format its extension says.
+    pub fn save(&self, path: &Path) -> Result<(), PatternError> {
+        let text = match Format::of(path) {
+            Format::Rle => self.to_rle()?,
+            Format::Plaintext => self.to_plaintext()?,
+            Format::Macrocell => self.to_macrocell(),
+        };
+
+        Ok(std::fs::write(path, text)?)
+    }
+
+    /// Parses a pattern in the plaintext format, see
+    /// <https://conwaylife.com/wiki/Plaintext>.
+    pub fn parse_plaintext(text: &str) -> Result<Self, PatternError> {
+        let mut alive = Vec::new();
+        let mut height = 0;
+
+        // Numbered before the comments are skipped, so that errors point at
+        // the right line
+        for (line_index, line) in text.lines().enumerate() {
+            if line.starts_with('!') {
+                continue;
+            }
+
+            for (x, character) in line.trim_end().chars().enumerate() {
+                match character {
+                    'O' | '*' => alive.push((x as u32, height)),
+                    '.' => {}
+                    c => {
+                        return Err(PatternError::UnexpectedCharacter {
+                            line: line_index + 1,
+                            character: c,
+                        })
+                    }
+                }
+            }
+
+            height += 1;
+        }
+
+        Ok(Self::from_alive(alive, (0, height), None, None))
+    }
+
+    /// Parses a pattern in Golly's run length encoded format, see
+    /// <https://conwaylife.com/wiki/Run_Length_Encoded>.
+    pub fn parse_rle(text: &str) -> Result<Self, PatternError> {
+        let mut header_size = (0, 0);
+        let mut rule = None;
+        let mut topology = None;
+        let mut header_seen = false;
+
+        let mut alive = Vec::new();
+        // Wider than the cells can be, so that no run can overflow them
+        let (mut x, mut y) = (0u64, 0u64);
+        let mut run = 0u32;
+
+        'lines: for (line_index, line) in text.lines().enumerate() {
+            let line_number = line_index + 1;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if !header_seen && line.starts_with('x') {
+                header_seen = true;
+
+                let mut in_rule = false;
+
+                for field in line.split(',') {
+                    let Some((key, value)) = field.split_once('=') else {
+                        // Golly's bounded grid sizes contain a comma, e.g. `B3/S23:T100,80`
+                        if in_rule {
+                            continue;
+                        }
+
+                        return Err(PatternError::InvalidHeader(line.to_string()));
+                    };
+                    in_rule = key.trim() == "rule";
+                    let value = value.trim();
+
+                    match key.trim() {
+                        "x" => header_size.0 = parse_dimension(value, line)?,
+                        "y" => header_size.1 = parse_dimension(value, line)?,
+                        "rule" => (rule, topology) = parse_rule(value)?,
+                        _ => return Err(PatternError::InvalidHeader(line.to_string())),
+                    }
+                }
+
+                continue;
+            }
+
+            for character in line.chars() {
+                if let Some(digit) = character.to_digit(10) {
+                    run = run
+                        .checked_mul(10)
+                        .and_then(|run| run.checked_add(digit))
+                        .ok_or(PatternError::RunTooLong { line: line_number })?;
+                    continue;
+                }
+
+                if character.is_whitespace() {
+                    continue;
+                }
+
+                // A missing run count means a single cell
+                let count = run.max(1) as u64;
+                run = 0;
+
+                match character {
+                    'b' | '.' => x += count,
+                    '$' => {
+                        y += count;
+                        x = 0;
+                    }
+                    '!' => break 'lines,
+                    // Multi-state files write alive cells as `A`
+                    'o' | 'A' => {
+                        if x + count > MAX_SIZE || alive.len() + count as usize > MAX_ALIVE {
+                            return Err(PatternError::TooLarge { line: line_number });
+                        }
+
+                        alive.extend((x..x + count).map(|x| (x as u32, y as u32)));
+                        x += count;
+                    }
+                    // States from 2 on, `B` to `X` and then prefixed by `p` to `y`
+                    'B'..='X' | 'p'..='y' => {
+                        return Err(PatternError::DyingState {
+                            line: line_number,
+                            character,
+                        })
+                    }
+                    c => {
+                        return Err(PatternError::UnexpectedCharacter {
+                            line: line_number,
+                            character: c,
+                        })
+                    }
+                }
+
+                if y >= MAX_SIZE {
+                    return Err(PatternError::TooLarge { line: line_number });
+                }
+            }
+        }
+
+        Ok(Self::from_alive(alive, header_size, rule, topology))
+    }
+
+    /// Parses a pattern in Golly's macrocell format, see
+    /// <https://conwaylife.com/wiki/Macrocell>.
+    pub fn parse_macrocell(text: &str) -> Result<Self, PatternError> {
+        let mut rule = None;
+        let mut topology = None;
+        let mut nodes = Vec::new();
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if let Some(value) = line.strip_prefix("#R") {
+                (rule, topology) = parse_rule(value.trim())?;
+            } else if !(line.is_empty() || line.starts_with(['#', '['])) {
+                nodes.push((line_index + 1, line));
+            }
+        }
+
+        // Only stored, so the rule doesn't matter
+        let mut universe = Universe::new(Rule::default());
+        universe.read_macrocell(nodes)?;
+
+        Ok(Self::from_universe(universe, rule, topology))
+    }
+
+    /// Builds a pattern at least `size` large from the positions of its alive
+    /// cells, which have to be below `MAX_SIZE`.
+    fn from_alive(
+        mut alive: Vec<(u32, u32)>,
+        size: (u32, u32),
+        rule: Option<Rule>,
+        topology: Option<Topology>,
+    ) -> Self {
+        // Trust the cells over the given size if the two disagree
+        let width = alive
+            .iter()
+            .map(|(x, _)| *x as u64 + 1)
+            .fold(size.0 as u64, u64::max);
+        let height = alive
+            .iter()
+            .map(|(_, y)| *y as u64 + 1)
+            .fold(size.1 as u64, u64::max);
+
+        alive.sort_unstable_by_key(|&(x, y)| (y, x));
+        alive.dedup();
+
+        Self {
+            width,
+            height,
+            cells: Cells::Alive(alive),
+            rule,
+            topology,
+        }
+    }
+
+    /// The rule with the topology appended unless it's the plane, as written
+    /// in the headers of RLE and macrocell files.
+    fn rule_string(&self) -> Option<String> {
+        let rule = self.rule?;
+
+        Some(
+            match self
+                .topology
+                .filter(|topology| *topology != Topology::Plane)
+            {
+                Some(topology) => format!("{rule}:{topology}"),
+                None => rule.to_string(),
+            },
+        )
+    }
+
+    /// The rows with alive cells from top to bottom, each with the columns of
+    /// its alive cells from left to right.
+    fn alive_rows(&self) -> Vec<(u32, Vec<u32>)> {
+        match &self.cells {
+            Cells::Alive(alive) => alive
+                .chunk_by(|a, b| a.1 == b.1)
+                .map(|row| (row[0].1, row.iter().map(|(x, _)| *x).collect()))
+                .collect(),
+            Cells::Quadtree { .. } => {
+                let mut alive = Vec::new();
+                self.for_each_alive(&mut |x, y| alive.push((x as u32, y as u32)));
+                alive.sort_unstable_by_key(|&(x, y)| (y, x));
+
+                alive
+                    .chunk_by(|a, b| a.1 == b.1)
+                    .map(|row| (row[0].1, row.iter().map(|(x, _)| *x).collect()))
+                    .collect()
+            }
+        }
+    }
+
+    /// Whether the pattern is small enough for RLE and plaintext files.
+    fn check_size(&self) -> Result<(), PatternError> {
+        if self.width > MAX_SIZE || self.height > MAX_SIZE {
+            return Err(PatternError::TooLargeToWrite {
+                width: self.width,
+                height: self.height,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn to_rle(&self) -> Result<String, PatternError> {
+        self.check_size()?;
+
+        let mut header = format!("x = {}, y = {}", self.width, self.height);
+
+        if let Some(rule) = self.rule_string() {
+            header += &format!(", rule = {rule}");
+        }
+
+        // Runs of (count, tag), with trailing dead cells dropped and empty rows
+        // merged into a single `$` run
+        let mut runs: Vec<(u32, char)> = Vec::new();
+        let mut push = |count: u32, tag: char| match runs.last_mut() {
+            Some((last_count, last_tag)) if *last_tag == tag => *last_count += count,
+            _ => runs.push((count, tag)),
+        };
+
+        let mut previous_y = 0;
+
+        for (y, columns) in self.alive_rows() {
+            if y > previous_y {
+                push(y - previous_y, '$');
+            }
+            previous_y = y;
+
+            let mut next_x = 0;
+
+            for x in columns {
+                if x > next_x {
+                    push(x - next_x, 'b');
+                }
+                push(1, 'o');
+                next_x = x + 1;
+            }
+        }
+
+        push(1, '!');
+
+        // Lines in RLE files should be at most 70 characters long
+        let mut body = String::new();
+        let mut line_length = 0;
+
+        for (count, tag) in runs {
+            let run = match count {
+                1 => tag.to_string(),
+                count => format!("{count}{tag}"),
+            };
+
+            if line_length + run.len() > 70 {
+                body.push('\n');
+                line_length = 0;
+            }
+
+            line_length += run.len();
+            body += &run;
+        }
+
+        Ok(format!("{header}\n{body}\n"))
+    }
+
+    pub fn to_plaintext(&self) -> Result<String, PatternError> {
+        self.check_size()?;
+
+        let mut text = String::from("!Name: subpixel-life\n");
+
+        let mut next_y = 0;
+
+        for (y, columns) in self.alive_rows() {
+            text.extend((next_y..y).map(|_| '\n'));
+            next_y = y + 1;
+
+            let mut next_x = 0;
+
+            for x in columns {
+                text.extend((next_x..x).map(|_| '.'));
+                text.push('O');
+                next_x = x + 1;
+            }
+
+            text.push('\n');
+        }
+
+        text.extend((next_y..self.height as u32).map(|_| '\n'));
+
+        Ok(text)
+    }
+
+    pub fn to_macrocell(&self) -> String {
+        let mut text = String::from("[M2] (subpixel-life)\n");
+
+        if let Some(rule) = self.rule_string() {
+            text += &format!("#R {rule}\n");
+        }
+
+        match &self.cells {
+            Cells::Quadtree { universe, .. } => text += &universe.write_macrocell(),
+            Cells::Alive(alive) => {
+                let mut universe = Universe::new(Rule::default());
+
+                // Only the blocks with alive cells in them, however far apart
+                let mut blocks: Vec<_> = alive
+                    .iter()
+                    .map(|&(x, y)| (x as i64 / 8 * 8, y as i64 / 8 * 8, 8, 8))
+                    .collect();
+                blocks.sort_unstable();
+                blocks.dedup();
+
+                universe.load_blocks(&blocks, &mut |x, y| {
+                    std::array::from_fn(|dy| {
+                        (0..8).fold(0, |bits, dx| {
+                            bits | ((self.get(x + dx, y + dy as i64) as u8) << dx)
+                        })
+                    })
+                });
+
+                text += &universe.write_macrocell();
+            }
+        }
+
+        text
+    }
+
+    /// Calls `f` with the position of every alive cell relative to the top
+    /// left of the pattern, without visiting the dead ones.
+    pub fn for_each_alive(&self, f: &mut dyn FnMut(i64, i64)) {
+        match &self.cells {
+            Cells::Alive(alive) => {
+                for &(x, y) in alive {
+                    f(x as i64, y as i64);
+                }
+            }
+            Cells::Quadtree {
+                universe,
+                left,
+                top,
+            } => universe.for_each_alive(&mut |x, y| f(x - left, y - top)),
+        }
+    }
+
+    /// Whether the cell at `(x, y)` relative to the top left of the pattern is
+    /// alive. Anything outside the pattern is dead.
+    pub fn get(&self, x: i64, y: i64) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return false;
+        }
+
+        match &self.cells {
+            Cells::Alive(alive) => alive
+                .binary_search_by_key(&(y as u32, x as u32), |&(x, y)| (y, x))
+                .is_ok(),
+            Cells::Quadtree {
+                universe,
+                left,
+                top,
+            } => universe.get(left + x, top + y),
+        }
+    }
+}
+
+fn parse_dimension(value: &str, line: &str) -> Result<u32, PatternError> {
+    value
+        .parse()
+        .map_err(|_| PatternError::InvalidHeader(line.to_string()))
+}
+
+/// Parses a rule with an optional topology appended, e.g. `B3/S23:T100,80`.
+/// Golly's grid sizes after the topology letter are ignored.
+fn parse_rule(value: &str) -> Result<(Option<Rule>, Option<Topology>), PatternError> {
+    let (rule, topology) = value.split_once(':').unwrap_or((value, ""));
+
+    let rule = rule.parse::<Rule>().map_err(PatternError::InvalidRule)?;
+
+    let topology = (!topology.is_empty())
+        .then(|| topology.parse::<Topology>())
+        .transpose()
+        .map_err(PatternError::InvalidTopology)?;
+
+    Ok((Some(rule), topology))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::splitmix64;
+
+    /// A random soup wide enough that its RLE has to be wrapped, cut down to
+    /// its alive cells.
+    fn soup() -> Pattern {
+        let cells: Vec<(i64, i64)> = (0..150 * 40)
+            .filter(|index| splitmix64(*index as u64).is_multiple_of(3))
+            .map(|index| (index % 150, index / 150))
+            .collect();
+
+        Pattern::from_cells(
+            &cells,
+            Some("B36/S23".parse().unwrap()),
+            Some(Topology::Torus),
+        )
+    }
+
+    fn alive(pattern: &Pattern) -> Vec<(u32, u32)> {
+        pattern
+            .alive_rows()
+            .into_iter()
+            .flat_map(|(y, columns)| columns.into_iter().map(move |x| (x, y)))
+            .collect()
+    }
+
+    fn assert_same(loaded: &Pattern, saved: &Pattern) {
+        assert_eq!((loaded.width, loaded.height), (saved.width, saved.height));
+        assert_eq!(alive(loaded), alive(saved));
+    }
+
+    #[test]
+    fn rle_round_trip() {
+        let pattern = soup();
+        let rle = pattern.to_rle().unwrap();
+
+        assert!(rle.lines().count() > 10);
+        assert!(rle.lines().skip(1).all(|line| line.len() <= 70));
+        assert!(rle.starts_with("x = 150, y = 40, rule = B36/S23:T\n"));
+
+        let loaded = Pattern::parse_rle(&rle).unwrap();
+        assert_same(&loaded, &pattern);
+        assert_eq!(loaded.rule, pattern.rule);
+        assert_eq!(loaded.topology, Some(Topology::Torus));
+    }
+
+    #[test]
+    fn plaintext_round_trip() {
+        let pattern = soup();
+        let loaded = Pattern::parse_plaintext(&pattern.to_plaintext().unwrap()).unwrap();
+        assert_same(&loaded, &pattern);
+    }
+
+    #[test]
+    fn macrocell_round_trip() {
+        let pattern = Pattern {
+            topology: Some(Topology::KleinBottle),
+            ..soup()
+        };
+
+        let loaded = Pattern::parse_macrocell(&pattern.to_macrocell()).unwrap();
+        assert_same(&loaded, &pattern);
+        assert_eq!(loaded.rule, pattern.rule);
+        assert_eq!(loaded.topology, Some(Topology::KleinBottle));
+    }
+
+    #[test]
+    fn far_apart_cells_save_as_macrocell() {
+        let cells = [(-5, 3), (1 << 30, 7), (12, 1 << 29)];
+        let pattern = Pattern::from_cells(&cells, None, None);
+        assert_eq!(
+            (pattern.width, pattern.height),
+            ((1 << 30) + 6, (1 << 29) - 2)
+        );
+
+        let loaded = Pattern::parse_macrocell(&pattern.to_macrocell()).unwrap();
+        assert_eq!(
+            (loaded.width, loaded.height),
+            (pattern.width, pattern.height)
+        );
+
+        for (x, y) in cells {
+            assert!(loaded.get(x + 5, y - 3));
+        }
+        assert!(!loaded.get(0, 1));
+
+        // Too wide for RLE and plaintext files to be of any use
+        for text in [pattern.to_rle(), pattern.to_plaintext()] {
+            assert!(matches!(
+                text,
+                Err(PatternError::TooLargeToWrite {
+                    width: 0x4000_0006,
+                    height: 0x1fff_fffe
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn cells_further_apart_than_u32_keep_their_places() {
+        let cells = [(-(1 << 40), 0), (1 << 40, 1 << 36), (0, -(1 << 33))];
+        let pattern = Pattern::from_cells(&cells, None, None);
+        assert_eq!(
+            (pattern.width, pattern.height),
+            ((1 << 41) + 1, (1 << 36) + (1 << 33) + 1)
+        );
+
+        for (x, y) in cells {
+            assert!(pattern.get(x + (1 << 40), y + (1 << 33)));
+        }
+        assert!(!pattern.get(0, 0));
+        assert!(!pattern.get(1 << 41, 0));
+    }
+
+    #[test]
+    fn golly_topology_size_is_ignored() {
+        let pattern = Pattern::parse_rle("x = 3, y = 1, rule = B3/S23:T100,80\n3o!\n").unwrap();
+        assert_eq!(pattern.topology, Some(Topology::Torus));
+        assert_eq!(alive(&pattern), [(0, 0), (1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn plaintext_errors_count_comment_lines() {
+        let result = Pattern::parse_plaintext("!Name: test\n!\n.O.\nOxO\n");
+        assert!(matches!(
+            result,
+            Err(PatternError::UnexpectedCharacter {
+                line: 4,
+                character: 'x'
+            })
+        ));
+    }
+}
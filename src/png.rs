@@ -0,0 +1,425 @@
+//! A minimal PNG encoder for 8 bit RGB images.
+//!
+//! The image data is compressed with deflate using the fixed Huffman codes and
+//! a greedy LZ77 matcher, which is plenty for life grids since they are mostly
+//! black with a lot of repetition.
+
+use std::{io, path::Path};
+
+/// Encodes `rgb`, three bytes per pixel in rows from the top, as a PNG file.
+pub fn encode(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    assert_eq!(rgb.len(), width as usize * height as usize * 3);
+
+    // Every scanline starts with its filter type, 0 meaning unfiltered
+    let mut scanlines = Vec::with_capacity(rgb.len() + height as usize);
+    for row in rgb.chunks_exact(width as usize * 3) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth 8, color type 2 (RGB), default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib(&scanlines));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub fn save(path: &Path, width: u32, height: u32, rgb: &[u8]) -> io::Result<()> {
+    std::fs::write(path, encode(width, height, rgb))
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                0xEDB88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    // 5552 is the most bytes that can be summed before `b` could overflow
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32K window and no preset dictionary
+    let mut out = vec![0x78, 0x01];
+    out.extend(deflate(data));
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    /// Writes the lowest `count` bits of `bits`, least significant first.
+    fn write(&mut self, bits: u32, count: u32) {
+        self.buffer |= (bits as u64) << self.count;
+        self.count += count;
+
+        while self.count >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a Huffman code, which deflate stores most significant bit first.
+    fn write_code(&mut self, code: u32, length: u32) {
+        self.write(code.reverse_bits() >> (32 - length), length);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+const WINDOW: usize = 32768;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 32;
+const HASH_BITS: u32 = 15;
+
+/// Writes a literal or length symbol with the fixed Huffman code.
+fn write_symbol(writer: &mut BitWriter, symbol: u32) {
+    match symbol {
+        0..=143 => writer.write_code(0x30 + symbol, 8),
+        144..=255 => writer.write_code(0x190 + symbol - 144, 9),
+        256..=279 => writer.write_code(symbol - 256, 7),
+        _ => writer.write_code(0xC0 + symbol - 280, 8),
+    }
+}
+
+fn write_match(writer: &mut BitWriter, length: usize, distance: usize) {
+    let code = LENGTH_BASES.partition_point(|base| *base as usize <= length) - 1;
+    write_symbol(writer, 257 + code as u32);
+    writer.write(
+        (length - LENGTH_BASES[code] as usize) as u32,
+        LENGTH_EXTRA_BITS[code] as u32,
+    );
+
+    let code = DISTANCE_BASES.partition_point(|base| *base as usize <= distance) - 1;
+    writer.write_code(code as u32, 5);
+    writer.write(
+        (distance - DISTANCE_BASES[code] as usize) as u32,
+        DISTANCE_EXTRA_BITS[code] as u32,
+    );
+}
+
+fn hash(data: &[u8]) -> usize {
+    let value = u32::from_le_bytes([data[0], data[1], data[2], 0]);
+    (value.wrapping_mul(0x9E3779B1) >> (32 - HASH_BITS)) as usize
+}
+
+fn insert(data: &[u8], position: usize, head: &mut [usize], previous: &mut [usize]) {
+    if position + 3 <= data.len() {
+        let hash = hash(&data[position..]);
+        previous[position % WINDOW] = head[hash];
+        head[hash] = position;
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter {
+        bytes: Vec::with_capacity(data.len() / 8),
+        buffer: 0,
+        count: 0,
+    };
+
+    // A single final block using the fixed Huffman codes
+    writer.write(1, 1);
+    writer.write(1, 2);
+
+    // The most recent position of every hash and the previous position with
+    // the same hash for every position in the window
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut previous = vec![usize::MAX; WINDOW];
+
+    let mut position = 0;
+
+    while position < data.len() {
+        let mut best = (0, 0);
+
+        if position + 3 <= data.len() {
+            let mut candidate = head[hash(&data[position..])];
+            let max_length = MAX_MATCH.min(data.len() - position);
+
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || position - candidate > WINDOW - 1 {
+                    break;
+                }
+
+                let length = data[candidate..]
+                    .iter()
+                    .zip(&data[position..position + max_length])
+                    .take_while(|(a, b)| a == b)
+                    .count();
+
+                if length > best.0 {
+                    best = (length, position - candidate);
+
+                    if length == max_length {
+                        break;
+                    }
+                }
+
+                let next = previous[candidate % WINDOW];
+                if next == usize::MAX || next >= candidate {
+                    break;
+                }
+                candidate = next;
+            }
+        }
+
+        let (length, distance) = best;
+
+        if length >= 3 {
+            write_match(&mut writer, length, distance);
+
+            for offset in 0..length {
+                insert(data, position + offset, &mut head, &mut previous);
+            }
+            position += length;
+        } else {
+            write_symbol(&mut writer, data[position] as u32);
+            insert(data, position, &mut head, &mut previous);
+            position += 1;
+        }
+    }
+
+    // End of block
+    write_symbol(&mut writer, 256);
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::splitmix64;
+
+    /// Reads the bits of a deflate stream, least significant first.
+    struct BitReader<'a> {
+        bytes: &'a [u8],
+        position: usize,
+    }
+
+    impl BitReader<'_> {
+        fn bit(&mut self) -> u32 {
+            let bit = (self.bytes[self.position / 8] >> (self.position % 8)) & 1;
+            self.position += 1;
+            bit as u32
+        }
+
+        fn bits(&mut self, count: u32) -> u32 {
+            (0..count).fold(0, |bits, index| bits | self.bit() << index)
+        }
+
+        /// Reads a Huffman code, which deflate stores most significant bit first.
+        fn code(&mut self, length: u32) -> u32 {
+            (0..length).fold(0, |code, _| code << 1 | self.bit())
+        }
+
+        /// Reads a literal or length symbol with the fixed Huffman code.
+        fn symbol(&mut self) -> u32 {
+            let code = self.code(7);
+            if code <= 0x17 {
+                return 256 + code;
+            }
+
+            let code = code << 1 | self.bit();
+            match code {
+                0x30..=0xBF => code - 0x30,
+                0xC0..=0xC7 => 280 + code - 0xC0,
+                _ => 144 + (code << 1 | self.bit()) - 0x190,
+            }
+        }
+    }
+
+    /// Just enough of inflate to read back what `deflate` writes: a single
+    /// final block with the fixed Huffman codes.
+    fn inflate(stream: &[u8]) -> Vec<u8> {
+        let mut reader = BitReader {
+            bytes: stream,
+            position: 0,
+        };
+        assert_eq!(reader.bits(1), 1, "final block");
+        assert_eq!(reader.bits(2), 1, "fixed Huffman codes");
+
+        let mut data = Vec::new();
+
+        loop {
+            let symbol = reader.symbol() as usize;
+
+            match symbol {
+                0..=255 => data.push(symbol as u8),
+                256 => break,
+                _ => {
+                    let code = symbol - 257;
+                    let length = LENGTH_BASES[code] as usize
+                        + reader.bits(LENGTH_EXTRA_BITS[code] as u32) as usize;
+
+                    let code = reader.code(5) as usize;
+                    let distance = DISTANCE_BASES[code] as usize
+                        + reader.bits(DISTANCE_EXTRA_BITS[code] as u32) as usize;
+
+                    assert!(distance <= data.len() && distance < WINDOW);
+                    for _ in 0..length {
+                        data.push(data[data.len() - distance]);
+                    }
+                }
+            }
+        }
+
+        // Nothing but the padding to the next byte is left
+        assert_eq!(reader.position.div_ceil(8), stream.len());
+        data
+    }
+
+    /// Stripes with runs of every length, broken up by random noise.
+    fn image(width: u32, height: u32) -> Vec<u8> {
+        (0..width as u64 * height as u64 * 3)
+            .map(|index| {
+                let hash = splitmix64(index);
+                if hash.is_multiple_of(7) {
+                    (hash >> 8) as u8
+                } else {
+                    (index / 3 % 300 / 40) as u8 * 30
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn checksums_match_known_answers() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"123456789"), 0x091E01DE);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+
+        // Long enough that the sums have to be reduced along the way
+        let data = vec![0xFF; 100_000];
+        let (a, b) = data.iter().fold((1u64, 0u64), |(a, b), byte| {
+            let a = (a + *byte as u64) % 65521;
+            (a, (b + a) % 65521)
+        });
+        assert_eq!(adler32(&data), (b << 16 | a) as u32);
+    }
+
+    #[test]
+    fn deflate_inflates_back() {
+        for data in [
+            Vec::new(),
+            b"a".to_vec(),
+            vec![0; 1000],
+            image(50, 40),
+            // Long enough that matches have to stay inside the window
+            image(400, 300),
+        ] {
+            assert_eq!(inflate(&deflate(&data)), data);
+        }
+    }
+
+    #[test]
+    fn encodes_image_as_scanlines() {
+        let (width, height) = (37, 21);
+        let rgb = image(width, height);
+        let png = encode(width, height, &rgb);
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+        // Every chunk as its kind and data, checking the CRCs on the way
+        let mut chunks = Vec::new();
+        let mut rest = &png[8..];
+        while !rest.is_empty() {
+            let length = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+            let (kind_and_data, crc) = rest[4..8 + length + 4].split_at(4 + length);
+            assert_eq!(
+                crc32(kind_and_data),
+                u32::from_be_bytes(crc.try_into().unwrap())
+            );
+
+            chunks.push((&kind_and_data[..4], &kind_and_data[4..]));
+            rest = &rest[12 + length..];
+        }
+
+        let kinds: Vec<_> = chunks.iter().map(|(kind, _)| *kind).collect();
+        assert_eq!(kinds, [b"IHDR", b"IDAT", b"IEND"]);
+        assert_eq!(chunks[0].1, [0, 0, 0, 37, 0, 0, 0, 21, 8, 2, 0, 0, 0]);
+
+        let zlib = chunks[1].1;
+        assert_eq!(u16::from_be_bytes([zlib[0], zlib[1]]) % 31, 0);
+        let scanlines = inflate(&zlib[2..zlib.len() - 4]);
+        assert_eq!(
+            u32::from_be_bytes(zlib[zlib.len() - 4..].try_into().unwrap()),
+            adler32(&scanlines)
+        );
+
+        let rows: Vec<_> = scanlines.chunks(1 + width as usize * 3).collect();
+        assert_eq!(rows.len(), height as usize);
+        for (row, pixels) in rows.iter().zip(rgb.chunks(width as usize * 3)) {
+            assert_eq!(row[0], 0, "unfiltered");
+            assert_eq!(&row[1..], pixels);
+        }
+    }
+}
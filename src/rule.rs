@@ -0,0 +1,696 @@
+use std::{fmt, str::FromStr};
+
+/// A life-like rule, e.g. Conway's `B3/S23`, one of the Generations family
+/// like Brian's Brain, `B2/S/C3`, or an isotropic non-totalistic rule written
+/// in Hensel's notation like `B2-a/S12`.
+///
+/// The rule is stored as a lookup table indexed by the current state of a cell
+/// and the configuration of its alive neighbors, so `GameOfLife::tick` never
+/// has to branch on the rule itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rule {
+    /// Indexed by 256 for alive cells plus the alive neighbors, one bit each
+    /// as given by `neighbor_bit`.
+    table: [bool; 512],
+    /// Whether a cell with each number of alive neighbors is born (the first
+    /// nine) or survives (the last nine) whatever their configuration.
+    totals: [bool; 18],
+    /// Whether a cell with each number of alive neighbors is born or survives,
+    /// in the same order, in some of their configurations but not all.
+    mixed: [bool; 18],
+    /// 2 for life-like rules. Generations rules have more, and a cell that
+    /// doesn't survive goes through every state from 2 to `states - 1` before
+    /// it is dead. Dying cells don't count as neighbors and can't be born.
+    states: u8,
+}
+
+impl Rule {
+    pub const CONWAY: Rule = Rule::new(&[3], &[2, 3]);
+
+    pub const fn new(birth: &[u8], survival: &[u8]) -> Self {
+        let mut totals = [false; 18];
+
+        let mut i = 0;
+        while i < birth.len() {
+            totals[birth[i] as usize] = true;
+            i += 1;
+        }
+
+        let mut i = 0;
+        while i < survival.len() {
+            totals[9 + survival[i] as usize] = true;
+            i += 1;
+        }
+
+        let mut table = [false; 512];
+
+        let mut i = 0;
+        while i < 512 {
+            let count = (i as u8).count_ones() as usize;
+            table[i] = totals[(i / 256) * 9 + count];
+            i += 1;
+        }
+
+        Self {
+            table,
+            totals,
+            mixed: [false; 18],
+            states: 2,
+        }
+    }
+
+    /// The rule where a dead cell is born when its alive neighbors are one of
+    /// the configurations in `birth` and an alive one survives when they are
+    /// one of those in `survival`, both indexed as by `neighbor_bit`.
+    fn from_configurations(birth: &[bool; 256], survival: &[bool; 256]) -> Self {
+        let mut table = [false; 512];
+        table[..256].copy_from_slice(birth);
+        table[256..].copy_from_slice(survival);
+
+        let mut totals = [true; 18];
+        let mut mixed = [false; 18];
+
+        for (index, next) in table.iter().enumerate() {
+            let total = (index / 256) * 9 + (index as u8).count_ones() as usize;
+            totals[total] &= *next;
+            mixed[total] |= *next != table[(index / 256) * 256 + ((1 << (total % 9)) - 1)];
+        }
+
+        Self {
+            table,
+            totals,
+            mixed,
+            states: 2,
+        }
+    }
+
+    /// The same rule with `states` states, from 2 to 255.
+    pub const fn with_states(self, states: u8) -> Self {
+        Self { states, ..self }
+    }
+
+    /// Whether a cell is alive in the next generation, `neighbors` having a
+    /// bit set for every alive neighbor as given by `neighbor_bit`.
+    #[inline(always)]
+    pub fn next_state(&self, alive: bool, neighbors: u8) -> bool {
+        self.table[alive as usize * 256 + neighbors as usize]
+    }
+
+    /// Whether a dead cell is born with each number of alive neighbors, in
+    /// every configuration of them.
+    pub fn birth(&self) -> &[bool] {
+        &self.totals[..9]
+    }
+
+    /// Whether an alive cell survives with each number of alive neighbors, in
+    /// every configuration of them.
+    pub fn survival(&self) -> &[bool] {
+        &self.totals[9..]
+    }
+
+    /// Whether a dead cell is born with each number of alive neighbors in some
+    /// of their configurations but not in others.
+    pub fn mixed_birth(&self) -> &[bool] {
+        &self.mixed[..9]
+    }
+
+    /// Whether an alive cell survives with each number of alive neighbors in
+    /// some of their configurations but not in others.
+    pub fn mixed_survival(&self) -> &[bool] {
+        &self.mixed[9..]
+    }
+
+    pub fn states(&self) -> u8 {
+        self.states
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Self::CONWAY
+    }
+}
+
+/// The bit standing for the neighbor at `(dx, dy)` in the configurations of
+/// `Rule::next_state`, counting from 0 for the top left neighbor to 7 for the
+/// bottom right one in reading order.
+#[inline(always)]
+pub fn neighbor_bit(dx: i64, dy: i64) -> u8 {
+    let index = (dy + 1) * 3 + dx + 1;
+
+    // The cell itself, in the middle, has no bit
+    1 << (index - (index > 4) as i64)
+}
+
+/// The letters Hensel's notation uses for every configuration of 1 to 4 alive
+/// neighbors that is different from the others once rotated and reflected,
+/// each with one of them. Those of 5 to 7 neighbors have the same letters as
+/// the configurations of their dead neighbors.
+const HENSEL_LETTERS: [&[(char, u8)]; 5] = [
+    &[],
+    &[('c', 0b0000_0001), ('e', 0b0000_0010)],
+    &[
+        ('c', 0b0000_0101),
+        ('e', 0b0000_1010),
+        ('k', 0b0001_0001),
+        ('a', 0b0000_0011),
+        ('i', 0b0001_1000),
+        ('n', 0b0010_0100),
+    ],
+    &[
+        ('c', 0b0010_0101),
+        ('e', 0b0001_1010),
+        ('k', 0b0011_0010),
+        ('a', 0b0000_1011),
+        ('i', 0b0000_0111),
+        ('n', 0b0000_1101),
+        ('y', 0b0011_0001),
+        ('q', 0b0010_0110),
+        ('j', 0b0000_1110),
+        ('r', 0b0001_1001),
+    ],
+    &[
+        ('c', 0b1010_0101),
+        ('e', 0b0101_1010),
+        ('k', 0b0011_0011),
+        ('a', 0b0000_1111),
+        ('i', 0b0001_1101),
+        ('n', 0b0010_0111),
+        ('y', 0b0011_0101),
+        ('q', 0b0011_0110),
+        ('j', 0b0011_1010),
+        ('r', 0b0001_1011),
+        ('t', 0b0011_1001),
+        ('w', 0b0010_1110),
+        ('z', 0b0011_1100),
+    ],
+];
+
+/// The letters of the configurations of `count` alive neighbors in Hensel's
+/// notation, each with every configuration it stands for, some of them more
+/// than once.
+fn hensel_letters(count: u8) -> impl Iterator<Item = (char, [u8; 8])> {
+    let (letters, invert) = match count {
+        0..=4 => (HENSEL_LETTERS[count as usize], 0),
+        5..=7 => (HENSEL_LETTERS[8 - count as usize], !0),
+        _ => (HENSEL_LETTERS[0], 0),
+    };
+
+    letters
+        .iter()
+        .map(move |(letter, configuration)| (*letter, symmetries(configuration ^ invert)))
+}
+
+fn is_hensel_letter(c: char) -> bool {
+    HENSEL_LETTERS[4].iter().any(|(letter, _)| *letter == c)
+}
+
+/// A configuration of alive neighbors turned and flipped in all 8 ways.
+fn symmetries(configuration: u8) -> [u8; 8] {
+    const NEIGHBORS: [(i64, i64); 8] = [
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+        (-1, 0),
+        (1, 0),
+        (-1, 1),
+        (0, 1),
+        (1, 1),
+    ];
+
+    std::array::from_fn(|symmetry| {
+        NEIGHBORS
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| (configuration >> bit) & 1 == 1)
+            .fold(0, |image, (_, &(dx, dy))| {
+                let (dx, dy) = if symmetry & 4 != 0 {
+                    (dy, dx)
+                } else {
+                    (dx, dy)
+                };
+                let dx = if symmetry & 1 != 0 { -dx } else { dx };
+                let dy = if symmetry & 2 != 0 { -dy } else { dy };
+                image | neighbor_bit(dx, dy)
+            })
+    })
+}
+
+/// Writes the birth or survival part of a rule, `configurations` telling
+/// which configurations of alive neighbors it includes.
+fn write_configurations(f: &mut fmt::Formatter<'_>, configurations: &[bool]) -> fmt::Result {
+    for count in 0..=8 {
+        let included: Vec<bool> = (0..=255u8)
+            .filter(|configuration| configuration.count_ones() == count as u32)
+            .map(|configuration| configurations[configuration as usize])
+            .collect();
+
+        if !included.contains(&true) {
+            continue;
+        }
+
+        write!(f, "{count}")?;
+
+        if !included.contains(&false) {
+            continue;
+        }
+
+        let (mut present, mut absent): (Vec<_>, Vec<_>) = hensel_letters(count)
+            .partition(|(_, symmetries)| configurations[symmetries[0] as usize]);
+
+        // In alphabetical order like Golly, so that its rules come out as
+        // they went in
+        present.sort_unstable_by_key(|(letter, _)| *letter);
+        absent.sort_unstable_by_key(|(letter, _)| *letter);
+
+        // Whichever is shorter, the letters included or `-` and those left out
+        if present.len() <= absent.len() {
+            for (letter, _) in present {
+                write!(f, "{letter}")?;
+            }
+        } else {
+            write!(f, "-")?;
+            for (letter, _) in absent {
+                write!(f, "{letter}")?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "B")?;
+        write_configurations(f, &self.table[..256])?;
+
+        write!(f, "/S")?;
+        write_configurations(f, &self.table[256..])?;
+
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleParseError {
+    Empty,
+    InvalidCharacter(char),
+    InvalidNeighborCount(char),
+    MissingPrefix(char),
+    DuplicateSection(char),
+    WrongSectionCount(usize),
+    InvalidStates(String),
+    /// A letter of Hensel's notation that doesn't go with the neighbor count
+    /// it follows, as in `1a`.
+    InvalidLetter(u8, char),
+}
+
+impl fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "the rule is empty"),
+            Self::InvalidCharacter(c) => write!(f, "unexpected character '{c}' in rule"),
+            Self::InvalidNeighborCount(c) => {
+                write!(f, "'{c}' is not a valid neighbor count, expected 0 to 8")
+            }
+            Self::MissingPrefix(c) => {
+                write!(f, "neighbor count '{c}' is not preceded by 'B' or 'S'")
+            }
+            Self::DuplicateSection(c) => write!(f, "the '{c}' section appears more than once"),
+            Self::WrongSectionCount(count) => write!(
+                f,
+                "expected two or three sections separated by '/' (survival/birth or survival/birth/states), found {count}"
+            ),
+            Self::InvalidStates(states) => write!(
+                f,
+                "\"{states}\" is not a valid number of states, expected 2 to 255"
+            ),
+            Self::InvalidLetter(count, letter) => {
+                let letters: String = hensel_letters(*count).map(|(letter, _)| letter).collect();
+
+                if letters.is_empty() {
+                    write!(f, "'{letter}' can't follow {count}, which takes no letters")
+                } else {
+                    write!(f, "'{letter}' can't follow {count}, expected one of \"{letters}\"")
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuleParseError {}
+
+fn neighbor_count(c: char) -> Result<u8, RuleParseError> {
+    match c.to_digit(10) {
+        Some(count @ 0..=8) => Ok(count as u8),
+        Some(_) => Err(RuleParseError::InvalidNeighborCount(c)),
+        None => Err(RuleParseError::InvalidCharacter(c)),
+    }
+}
+
+fn state_count(s: &str) -> Result<u8, RuleParseError> {
+    match s.parse() {
+        Ok(states @ 2..=255) => Ok(states),
+        _ => Err(RuleParseError::InvalidStates(s.to_string())),
+    }
+}
+
+/// The configurations of alive neighbors in a birth or survival section,
+/// either whole neighbor counts as in `23` or, in Hensel's notation, counts
+/// followed by the letters of the configurations they are limited to as in
+/// `2ce`, or by `-` and those they leave out as in `2-a`.
+fn configurations(section: &str) -> Result<[bool; 256], RuleParseError> {
+    let mut configurations = [false; 256];
+    let mut chars = section.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let count = neighbor_count(c)?;
+        let without = chars.next_if_eq(&'-').is_some();
+        let mut chosen = [false; 256];
+        let mut lettered = false;
+
+        while let Some(letter) = chars.next_if(|c| is_hensel_letter(*c)) {
+            let (_, symmetries) = hensel_letters(count)
+                .find(|(candidate, _)| *candidate == letter)
+                .ok_or(RuleParseError::InvalidLetter(count, letter))?;
+
+            for configuration in symmetries {
+                chosen[configuration as usize] = true;
+            }
+
+            lettered = true;
+        }
+
+        if without && !lettered {
+            return Err(RuleParseError::InvalidCharacter('-'));
+        }
+
+        for configuration in 0..=255u8 {
+            let included = !lettered || chosen[configuration as usize] != without;
+
+            if configuration.count_ones() == count as u32 && included {
+                configurations[configuration as usize] = true;
+            }
+        }
+    }
+
+    Ok(configurations)
+}
+
+impl FromStr for Rule {
+    type Err = RuleParseError;
+
+    /// Accepts both `B36/S23` style rules (letters in any case and order, the
+    /// `/` is optional) and the older `23/36` survival/birth notation, either
+    /// followed by the number of states of a Generations rule as in `B2/S/C3`
+    /// and `345/2/4`. Neighbor counts can be narrowed down with the lowercase
+    /// letters of Hensel's notation, as in `B2-a/S12`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if s.is_empty() {
+            return Err(RuleParseError::Empty);
+        }
+
+        let mut birth = String::new();
+        let mut survival = String::new();
+        let mut states = None;
+
+        if s.contains(|c: char| c.eq_ignore_ascii_case(&'b') || c.eq_ignore_ascii_case(&'s')) {
+            let mut section: Option<&mut String> = None;
+            let mut seen_birth = false;
+            let mut seen_survival = false;
+            let mut seen_states = false;
+            // The digits after `C` make up a single number rather than counts
+            let mut in_states = false;
+            let mut state_digits = String::new();
+            // Hensel's letters, `c` among them, only ever follow a count
+            let mut after_count = false;
+
+            for c in s.chars() {
+                match c {
+                    _ if after_count && (c == '-' || is_hensel_letter(c)) => {
+                        if let Some(section) = section.as_mut() {
+                            section.push(c);
+                        }
+                        continue;
+                    }
+                    'B' | 'b' => {
+                        if seen_birth {
+                            return Err(RuleParseError::DuplicateSection('B'));
+                        }
+                        seen_birth = true;
+                        in_states = false;
+                        section = Some(&mut birth);
+                    }
+                    'S' | 's' => {
+                        if seen_survival {
+                            return Err(RuleParseError::DuplicateSection('S'));
+                        }
+                        seen_survival = true;
+                        in_states = false;
+                        section = Some(&mut survival);
+                    }
+                    'C' | 'c' => {
+                        if seen_states {
+                            return Err(RuleParseError::DuplicateSection('C'));
+                        }
+                        seen_states = true;
+                        in_states = true;
+                        section = None;
+                    }
+                    '/' => in_states = false,
+                    _ if in_states => state_digits.push(c),
+                    _ => {
+                        neighbor_count(c)?;
+
+                        match section.as_mut() {
+                            Some(section) => section.push(c),
+                            None => return Err(RuleParseError::MissingPrefix(c)),
+                        }
+
+                        after_count = true;
+                        continue;
+                    }
+                }
+
+                after_count = false;
+            }
+
+            if seen_states {
+                states = Some(state_count(&state_digits)?);
+            }
+        } else {
+            let sections: Vec<&str> = s.split('/').collect();
+
+            if !(2..=3).contains(&sections.len()) {
+                return Err(RuleParseError::WrongSectionCount(sections.len()));
+            }
+
+            if let Some(digits) = sections.get(2) {
+                states = Some(state_count(digits)?);
+            }
+
+            survival = sections[0].to_string();
+            birth = sections[1].to_string();
+        }
+
+        let rule = Self::from_configurations(&configurations(&birth)?, &configurations(&survival)?);
+        Ok(rule.with_states(states.unwrap_or(2)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::life::splitmix64;
+
+    /// The neighbors of a cell in order around it, clockwise from the top left.
+    const RING: [(i64, i64); 8] = [
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+        (1, 0),
+        (1, 1),
+        (0, 1),
+        (-1, 1),
+        (-1, 0),
+    ];
+
+    /// Whether the two alive neighbors of a configuration of 2 are next to
+    /// each other, which is what `a` stands for.
+    fn is_adjacent_pair(configuration: u8) -> bool {
+        let alive: Vec<usize> = (0..8)
+            .filter(|index| {
+                let (dx, dy) = RING[*index];
+                configuration & neighbor_bit(dx, dy) != 0
+            })
+            .collect();
+
+        alive.len() == 2 && matches!(alive[1] - alive[0], 1 | 7)
+    }
+
+    #[test]
+    fn parses_known_rules() {
+        for text in [
+            "B2-a/S12",
+            "B3/S2-i34q",
+            "B2ce3-k/S1e23ak",
+            "B3-cnry4-acery5i/S23-a4-jknqr5y8",
+        ] {
+            let rule: Rule = text.parse().unwrap();
+            assert!(
+                rule.mixed_birth().contains(&true) || rule.mixed_survival().contains(&true),
+                "{text}"
+            );
+            assert_eq!(rule.to_string(), text);
+        }
+
+        // Just Friends is born on two neighbors unless they are next to each
+        // other, and survives on one or two whatever they are
+        let rule: Rule = "B2-a/S12".parse().unwrap();
+
+        for configuration in 0..=255u8 {
+            let count = configuration.count_ones();
+            let born = count == 2 && !is_adjacent_pair(configuration);
+
+            assert_eq!(rule.next_state(false, configuration), born);
+            assert_eq!(
+                rule.next_state(true, configuration),
+                count == 1 || count == 2
+            );
+        }
+    }
+
+    #[test]
+    fn letters_cover_every_configuration_once() {
+        let letter_counts = [0, 2, 6, 10, 13, 10, 6, 2, 0];
+
+        for count in 0..=8u8 {
+            let mut letters = [None; 256];
+
+            for (letter, configurations) in hensel_letters(count) {
+                for configuration in configurations {
+                    assert_eq!(configuration.count_ones(), count as u32);
+                    // Each configuration belongs to a single letter
+                    assert!(letters[configuration as usize].is_none_or(|other| other == letter));
+                    letters[configuration as usize] = Some(letter);
+
+                    // Turning or mirroring any of them gives one of the others
+                    assert!(symmetries(configuration)
+                        .iter()
+                        .all(|image| configurations.contains(image)));
+                }
+            }
+
+            assert_eq!(hensel_letters(count).count(), letter_counts[count as usize]);
+
+            if letter_counts[count as usize] > 0 {
+                for configuration in 0..=255u8 {
+                    let lettered = letters[configuration as usize].is_some();
+                    assert_eq!(lettered, configuration.count_ones() == count as u32);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn letters_and_their_exceptions_fill_the_table() {
+        for count in 1..=7u8 {
+            let whole: Rule = format!("B{count}/S").parse().unwrap();
+            let letters: String = hensel_letters(count).map(|(letter, _)| letter).collect();
+
+            let all: Rule = format!("B{count}{letters}/S").parse().unwrap();
+            assert_eq!(all, whole);
+
+            for letter in letters.chars() {
+                let only: Rule = format!("B{count}{letter}/S").parse().unwrap();
+                let without: Rule = format!("B{count}-{letter}/S").parse().unwrap();
+
+                for configuration in 0..=255u8 {
+                    assert_eq!(
+                        only.next_state(false, configuration)
+                            || without.next_state(false, configuration),
+                        whole.next_state(false, configuration)
+                    );
+                    assert!(
+                        !(only.next_state(false, configuration)
+                            && without.next_state(false, configuration))
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for text in [
+            "B3/S23",
+            "B36/S23",
+            "B2/S",
+            "B3678/S34678",
+            "B2/S/C3",
+            "B2-a/S12/C4",
+        ] {
+            assert_eq!(text.parse::<Rule>().unwrap().to_string(), text);
+        }
+
+        assert_eq!("23/3".parse::<Rule>().unwrap().to_string(), "B3/S23");
+
+        // Random rules with every count narrowed down to some of its letters
+        for seed in 0..200u64 {
+            let mut text = String::new();
+
+            for (section, prefix) in ["B", "/S"].into_iter().enumerate() {
+                text += prefix;
+
+                for count in 0..=8u8 {
+                    let bits = splitmix64(seed * 18 + section as u64 * 9 + count as u64);
+
+                    if bits.is_multiple_of(3) {
+                        continue;
+                    }
+
+                    text += &count.to_string();
+                    text.extend(
+                        hensel_letters(count)
+                            .enumerate()
+                            .filter(|(index, _)| (bits >> (index + 8)) & 1 == 1)
+                            .map(|(_, (letter, _))| letter),
+                    );
+                }
+            }
+
+            let rule: Rule = text.parse().unwrap();
+            assert_eq!(rule.to_string().parse::<Rule>().unwrap(), rule, "{text}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_letters() {
+        assert_eq!(
+            "B2z/S23".parse::<Rule>(),
+            Err(RuleParseError::InvalidLetter(2, 'z'))
+        );
+        assert_eq!(
+            "B1a/S23".parse::<Rule>(),
+            Err(RuleParseError::InvalidLetter(1, 'a'))
+        );
+        assert_eq!(
+            "B3/S0c".parse::<Rule>(),
+            Err(RuleParseError::InvalidLetter(0, 'c'))
+        );
+        assert_eq!(
+            "B2-/S23".parse::<Rule>(),
+            Err(RuleParseError::InvalidCharacter('-'))
+        );
+        assert_eq!(
+            "B2x/S23".parse::<Rule>(),
+            Err(RuleParseError::InvalidCharacter('x'))
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::{io, path::Path};
+
+use crate::{png, subpixel::Geometry};
+
+/// Saves a framebuffer of 0RGB pixels exactly as it was drawn, one image
+/// pixel per screen pixel.
+pub fn save_exact(path: &Path, pixels: &[u32], width: u32, height: u32) -> io::Result<()> {
+    let rgb: Vec<u8> = pixels
+        .iter()
+        .flat_map(|pixel| [(pixel >> 16) as u8, (pixel >> 8) as u8, *pixel as u8])
+        .collect();
+
+    png::save(path, width, height, &rgb)
+}
+
+/// Saves a framebuffer blown up so that every subpixel of `geometry` gets
+/// its own block, lit in its own color as bright as the framebuffer drives it.
+/// A pixel becomes a square with its subpixels in the same arrangement as on
+/// the screen, so unlike the exact screenshot this reads correctly at any
+/// zoom level.
+pub fn save_subpixel_view(
+    path: &Path,
+    pixels: &[u32],
+    width: u32,
+    height: u32,
+    geometry: &Geometry,
+) -> io::Result<()> {
+    let (cells_x, cells_y) = geometry.cells_per_pixel;
+
+    // Every subpixel is a block of `cells_y` by `cells_x` image pixels, which
+    // makes every pixel a square `scale` image pixels wide
+    let scale = cells_x * cells_y;
+    let (view_width, view_height) = (width * scale, height * scale);
+
+    let mut rgb = vec![0; view_width as usize * view_height as usize * 3];
+
+    for (y, row) in pixels.chunks_exact(width as usize).enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            let [_, red, green, blue] = pixel.to_be_bytes();
+            let channels = [red, green, blue].map(|channel| channel as f32 / 255.0);
+
+            for cell_y in 0..cells_y {
+                for cell_x in 0..cells_x {
+                    let weights =
+                        geometry.weights(x as u32 * cells_x + cell_x, y as u32 * cells_y + cell_y);
+
+                    // How brightly the framebuffer drives this subpixel, e.g. a
+                    // white subpixel only as bright as the dimmest channel
+                    let intensity = weights
+                        .iter()
+                        .zip(channels)
+                        .filter(|(weight, _)| **weight > 0.0)
+                        .map(|(weight, channel)| channel / weight)
+                        .fold(1.0f32, f32::min);
+
+                    let color = weights.map(|weight| (weight * intensity * 255.0).min(255.0) as u8);
+
+                    let left = x as u32 * scale + cell_x * cells_y;
+                    let top = y as u32 * scale + cell_y * cells_x;
+
+                    for block_y in top..top + cells_x {
+                        for block_x in left..left + cells_y {
+                            let index = (block_y * view_width + block_x) as usize * 3;
+                            rgb[index..index + 3].copy_from_slice(&color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    png::save(path, view_width, view_height, &rgb)
+}
@@ -0,0 +1,78 @@
+//! Settings remembered between runs, such as the layout picked in calibration
+//! mode. They are stored as `KEY = VALUE` lines in the user's config directory
+//! and only fill in what isn't given on the command line.
+
+use std::{io, path::PathBuf};
+
+use crate::subpixel::Layout;
+
+#[derive(Debug, Default)]
+pub struct Settings {
+    pub layout: Option<Layout>,
+}
+
+impl Settings {
+    /// `subpixel-life/config` in the platform's config directory, `None` if
+    /// there is no home directory to put it in.
+    pub fn path() -> Option<PathBuf> {
+        let directory = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+
+        Some(directory.join("subpixel-life").join("config"))
+    }
+
+    /// Loads the saved settings, or the defaults if nothing was saved yet.
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(format!("Could not read {}: {err}", path.display())),
+        };
+
+        let mut settings = Self::default();
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+
+            // Unknown keys are skipped so that older versions can read newer files
+            if let Some(("layout", value)) = line
+                .split_once('=')
+                .map(|(key, value)| (key.trim(), value.trim()))
+            {
+                settings.layout = Some(
+                    value
+                        .parse()
+                        .map_err(|err| format!("Invalid layout in {}: {err}", path.display()))?,
+                );
+            }
+        }
+
+        Ok(settings)
+    }
+
+    /// Saves the settings, returning where they were saved.
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let path = Self::path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "no config directory to save to")
+        })?;
+
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+
+        let mut text = String::from("# Written by subpixel-life\n");
+
+        if let Some(layout) = self.layout {
+            text += &format!("layout = {layout}\n");
+        }
+
+        std::fs::write(&path, text)?;
+        Ok(path)
+    }
+}
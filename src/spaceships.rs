@@ -0,0 +1,496 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    time::{Duration, Instant},
+};
+
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    census::{self, Behavior},
+    grid::Grid,
+    rule::Rule,
+};
+
+/// The longest period looked for. Searching runs every generation while
+/// spaceships are highlighted, so it is much shorter than the census's.
+const MAX_PERIOD: u64 = 128;
+
+/// Objects with more cells than this are assumed not to be spaceships, which
+/// keeps the search quick while a soup is still mostly chaos.
+const MAX_POPULATION: usize = 256;
+
+/// Objects wider or taller than this are assumed not to be spaceships either,
+/// so that a tile can be searched without reading far past it.
+const MAX_SIZE: i64 = 64;
+
+/// The width and height of the squares the grid is searched in.
+const TILE_SIZE: i64 = 64;
+
+/// About how long `SpaceshipFinder::search` spends every generation. It
+/// always searches at least one tile, which can take longer while a soup is
+/// still mostly chaos and every object in it is a new shape.
+const SEARCH_TIME: Duration = Duration::from_millis(5);
+
+/// How many shapes `SpaceshipFinder` remembers before starting over, as a
+/// chaotic grid makes new ones every generation.
+const MAX_KNOWN: usize = 100_000;
+
+/// An object that reappears moved after `period` generations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Spaceship {
+    pub apgcode: String,
+    /// The smallest rectangle `(left, top, right, bottom)` containing its
+    /// cells, with `right` and `bottom` just past it.
+    pub bounds: (i64, i64, i64, i64),
+    pub period: u64,
+    /// How far it moves every period.
+    pub dx: i64,
+    pub dy: i64,
+}
+
+impl Spaceship {
+    /// Its velocity as `(dx,dy)c/p`, e.g. `(1,-1)c/4` for a glider flying up
+    /// and to the right.
+    pub fn speed(&self) -> String {
+        speed(self.dx, self.dy, self.period)
+    }
+
+    /// Where it is `generations` later, counting whole periods only as those
+    /// are when it is back in the same shape.
+    fn flown(&self, generations: u64) -> Spaceship {
+        let periods = (generations / self.period) as i64;
+        let (dx, dy) = (self.dx * periods, self.dy * periods);
+        let (left, top, right, bottom) = self.bounds;
+
+        Spaceship {
+            bounds: (left + dx, top + dy, right + dx, bottom + dy),
+            ..self.clone()
+        }
+    }
+}
+
+impl fmt::Display for Spaceship {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (left, top, ..) = self.bounds;
+        write!(f, "{}", self.apgcode)?;
+
+        if let Some(name) = census::name(&self.apgcode) {
+            write!(f, " ({name})")?;
+        }
+
+        write!(f, " at {left},{top} moving at {}", self.speed())
+    }
+}
+
+/// A velocity of `(dx, dy)` cells every `period` generations in the usual
+/// notation, `c` being the speed of light of one cell per generation.
+pub fn speed(dx: i64, dy: i64, period: u64) -> String {
+    format!("({dx},{dy})c/{period}")
+}
+
+/// An object in a tile, which is the one its first cell is in.
+struct Object {
+    /// The leftmost of its cells, the topmost of those if there are several.
+    cell: (i64, i64),
+    /// The top left corner of its bounding box.
+    position: (i64, i64),
+    /// Its cells moved to the origin.
+    shape: Vec<(i64, i64)>,
+}
+
+/// A spaceship as it was when `SpaceshipFinder` found it.
+struct Sighting {
+    generation: u64,
+    /// Its first cell, see `Object`.
+    cell: (i64, i64),
+    spaceship: Spaceship,
+}
+
+impl Sighting {
+    /// The spaceship moved to where it is in `generation`, and the tile it
+    /// is in then.
+    fn flown(&self, generation: u64) -> ((i64, i64), Spaceship) {
+        let spaceship = self.spaceship.flown(generation - self.generation);
+        let dx = spaceship.bounds.0 - self.spaceship.bounds.0;
+        let dy = spaceship.bounds.1 - self.spaceship.bounds.1;
+
+        (tile_of((self.cell.0 + dx, self.cell.1 + dy)), spaceship)
+    }
+}
+
+/// Finds the spaceships in a grid, remembering every shape it has stepped
+/// so that objects seen before, in the same phase, are recognized at once.
+///
+/// Separating a big grid into objects takes far too long to do every
+/// generation, so `search` sweeps over it a few tiles at a time instead and
+/// moves the spaceships it found along until it gets back to them.
+#[derive(Default)]
+pub struct SpaceshipFinder {
+    known: HashMap<Vec<(i64, i64)>, Option<Spaceship>>,
+    /// The tiles left to search in the current sweep, the next one last.
+    queue: Vec<(i64, i64)>,
+    /// Every spaceship found so far.
+    found: Vec<Sighting>,
+}
+
+impl SpaceshipFinder {
+    /// Every object in `grid`, which is in `generation`, that flies off on
+    /// its own under `rule`, searching the whole grid at once.
+    pub fn find(&mut self, grid: &dyn Grid, rule: &Rule, generation: u64) -> Vec<Spaceship> {
+        self.clear();
+        self.queue = tiles(grid);
+        self.sweep(grid, rule, generation, Duration::MAX);
+        self.spaceships(generation)
+    }
+
+    /// Carries on sweeping over `grid` for about `SEARCH_TIME`, returning
+    /// every spaceship found so far moved to where it is in `generation`.
+    /// One that crashed is outlined until the sweep gets back to it.
+    pub fn search(&mut self, grid: &dyn Grid, rule: &Rule, generation: u64) -> Vec<Spaceship> {
+        if self.queue.is_empty() {
+            self.queue = tiles(grid);
+
+            // Forget spaceships that left the grid
+            let tiles: HashSet<_> = self.queue.iter().copied().collect();
+            self.found
+                .retain(|sighting| tiles.contains(&sighting.flown(generation).0));
+        }
+
+        self.sweep(grid, rule, generation, SEARCH_TIME);
+        self.spaceships(generation)
+    }
+
+    /// Forgets where spaceships were found and starts sweeping from the
+    /// beginning, for when the grid was changed all at once.
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.found.clear();
+    }
+
+    /// Searches tiles from `queue` until `time` is up.
+    fn sweep(&mut self, grid: &dyn Grid, rule: &Rule, generation: u64, time: Duration) {
+        let started = Instant::now();
+        let mut searched = HashSet::new();
+        let mut objects = Vec::new();
+
+        while let Some(tile) = self.queue.pop() {
+            let tile_objects = objects_in(grid, tile);
+            self.classify(&tile_objects, rule);
+
+            searched.insert(tile);
+            objects.extend(tile_objects);
+
+            if started.elapsed() >= time {
+                break;
+            }
+        }
+
+        // What was found in the searched tiles before is out of date, and
+        // whatever flew into them since was just found again
+        self.found
+            .retain(|sighting| !searched.contains(&sighting.flown(generation).0));
+
+        for object in objects {
+            let Some(spaceship) = self.known[&object.shape].clone() else {
+                continue;
+            };
+
+            // Remembered at the origin, so move it to where this one is
+            let (left, top) = object.position;
+            let (_, _, width, height) = spaceship.bounds;
+
+            self.found.push(Sighting {
+                generation,
+                cell: object.cell,
+                spaceship: Spaceship {
+                    bounds: (left, top, left + width, top + height),
+                    ..spaceship
+                },
+            });
+        }
+    }
+
+    /// Steps the shapes of `objects` that haven't been seen before.
+    fn classify(&mut self, objects: &[Object], rule: &Rule) {
+        if self.known.len() > MAX_KNOWN {
+            self.known.clear();
+        }
+
+        // A soup that is still mostly chaos has lots of shapes never seen
+        // before, each of which has to be stepped for up to `MAX_PERIOD`
+        let unknown: HashSet<&Vec<(i64, i64)>> = objects
+            .iter()
+            .map(|object| &object.shape)
+            .filter(|shape| !self.known.contains_key(*shape))
+            .collect();
+
+        let classified: Vec<_> = unknown
+            .into_par_iter()
+            .map(|shape| (shape.clone(), classify(shape, rule)))
+            .collect();
+
+        self.known.extend(classified);
+    }
+
+    /// The spaceships found so far where they are in `generation`, from the
+    /// top down.
+    fn spaceships(&self, generation: u64) -> Vec<Spaceship> {
+        let mut spaceships: Vec<_> = self
+            .found
+            .iter()
+            .map(|sighting| sighting.flown(generation).1)
+            .collect();
+
+        spaceships.sort_by_key(|spaceship| (spaceship.bounds.1, spaceship.bounds.0));
+        spaceships
+    }
+}
+
+/// Every tile with part of a region of `grid` in it, in the order a sweep
+/// searches them, the first one last.
+fn tiles(grid: &dyn Grid) -> Vec<(i64, i64)> {
+    let mut tiles = Vec::new();
+
+    for (left, top, width, height) in grid.regions() {
+        if width == 0 || height == 0 {
+            continue;
+        }
+
+        let right = (left + width as i64 - 1).div_euclid(TILE_SIZE);
+        let bottom = (top + height as i64 - 1).div_euclid(TILE_SIZE);
+
+        for y in top.div_euclid(TILE_SIZE)..=bottom {
+            for x in left.div_euclid(TILE_SIZE)..=right {
+                tiles.push((x, y));
+            }
+        }
+    }
+
+    tiles.sort_unstable_by_key(|&(x, y)| std::cmp::Reverse((y, x)));
+    tiles.dedup();
+    tiles
+}
+
+/// The tile the cell at `(x, y)` is in.
+fn tile_of((x, y): (i64, i64)) -> (i64, i64) {
+    (x.div_euclid(TILE_SIZE), y.div_euclid(TILE_SIZE))
+}
+
+/// The normalized objects of up to `MAX_POPULATION` cells and `MAX_SIZE`
+/// wide and tall whose first cell is in `tile`.
+fn objects_in(grid: &dyn Grid, (x, y): (i64, i64)) -> Vec<Object> {
+    let (left, top) = (x * TILE_SIZE, y * TILE_SIZE);
+
+    // Cells at most two apart belong to the same object, so reading two more
+    // columns to the left shows which objects reach out of the tile there,
+    // and two past `MAX_SIZE` on the other sides which are too big, as those
+    // are the only ones the edges of what is read cut off
+    let (window_left, window_top) = (left - 2, top - MAX_SIZE - 2);
+    let (width, height) = (TILE_SIZE + MAX_SIZE + 4, TILE_SIZE + 2 * MAX_SIZE + 4);
+
+    let mut cells = Vec::new();
+    let mut row = vec![false; width as usize];
+
+    for y in window_top..window_top + height {
+        grid.copy_row(y, window_left, &mut row);
+
+        cells.extend(
+            row.iter()
+                .enumerate()
+                .filter(|(_, alive)| **alive)
+                .map(|(x, _)| (window_left + x as i64, y)),
+        );
+    }
+
+    census::separate(&cells)
+        .into_iter()
+        .filter(|object| object.len() <= MAX_POPULATION)
+        .map(|object| {
+            let ((left, top), shape) = census::normalize(object.into_iter());
+            let (x, y) = shape[0];
+
+            Object {
+                cell: (left + x, top + y),
+                position: (left, top),
+                shape,
+            }
+        })
+        .filter(|object| {
+            let width = object.shape.iter().map(|(x, _)| x + 1).max().unwrap_or(0);
+            let height = object.shape.iter().map(|(_, y)| y + 1).max().unwrap_or(0);
+
+            tile_of(object.cell) == (x, y) && width <= MAX_SIZE && height <= MAX_SIZE
+        })
+        .collect()
+}
+
+/// The spaceship with the cells of `shape`, if it is one.
+fn classify(shape: &[(i64, i64)], rule: &Rule) -> Option<Spaceship> {
+    let classification = census::classify_within(shape, rule, MAX_PERIOD, MAX_POPULATION);
+
+    let Behavior::Spaceship { period, dx, dy } = classification.behavior else {
+        return None;
+    };
+
+    // Normalized shapes start at the origin
+    let right = shape.iter().map(|(x, _)| x + 1).max().unwrap_or(0);
+    let bottom = shape.iter().map(|(_, y)| y + 1).max().unwrap_or(0);
+
+    Some(Spaceship {
+        apgcode: classification.apgcode,
+        bounds: (0, 0, right, bottom),
+        period,
+        dx,
+        dy,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{Backend, Neighborhood};
+    use crate::topology::Topology;
+
+    /// A glider flying down and to the right.
+    const GLIDER: [(i64, i64); 5] = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+
+    /// A lightweight spaceship flying left.
+    const LWSS: [(i64, i64); 9] = [
+        (1, 0),
+        (4, 0),
+        (0, 1),
+        (0, 2),
+        (4, 2),
+        (0, 3),
+        (1, 3),
+        (2, 3),
+        (3, 3),
+    ];
+
+    /// A grid with two gliders and a lightweight spaceship flying different
+    /// ways between a block, a blinker and a beacon, the spaceship across the
+    /// edge between two tiles.
+    fn grid(backend: Backend) -> Box<dyn Grid> {
+        let mut grid = backend.create(256, 256, 2);
+
+        let mut place = |cells: &[(i64, i64)], (left, top): (i64, i64)| {
+            for &(x, y) in cells {
+                grid.set(left + x, top + y, true);
+            }
+        };
+
+        let glider_up: Vec<_> = GLIDER.iter().map(|&(x, y)| (x, 2 - y)).collect();
+
+        place(&GLIDER, (10, 10));
+        place(&glider_up, (60, 200));
+        place(&LWSS, (126, 100));
+        place(&[(0, 0), (1, 0), (0, 1), (1, 1)], (200, 30));
+        place(&[(0, 0), (1, 0), (2, 0)], (200, 120));
+        place(&[(0, 0), (1, 0), (0, 1), (3, 2), (2, 3), (3, 3)], (62, 62));
+
+        grid
+    }
+
+    fn summary(spaceships: &[Spaceship]) -> Vec<String> {
+        spaceships
+            .iter()
+            .map(|spaceship| {
+                let (left, top, right, bottom) = spaceship.bounds;
+                let speed = spaceship.speed();
+                format!(
+                    "{} {left},{top} {right},{bottom} {speed}",
+                    spaceship.apgcode
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_spaceships_with_their_velocities() {
+        let rule = Rule::default();
+
+        for backend in [Backend::Packed, Backend::Sparse] {
+            let grid = grid(backend);
+            let spaceships = SpaceshipFinder::default().find(grid.as_ref(), &rule, 0);
+
+            assert_eq!(
+                summary(&spaceships),
+                [
+                    "xq4_153 10,10 13,13 (1,1)c/4",
+                    "xq4_6frc 126,100 131,104 (-2,0)c/4",
+                    "xq4_153 60,200 63,203 (1,-1)c/4",
+                ],
+                "{backend}"
+            );
+        }
+    }
+
+    #[test]
+    fn moves_spaceships_along_until_swept_again() {
+        let rule = Rule::default();
+        let mut grid = grid(Backend::Packed);
+        let mut finder = SpaceshipFinder::default();
+
+        let found = finder.find(grid.as_ref(), &rule, 0);
+
+        for _ in 0..8 {
+            grid.step(&rule, Topology::Plane, Neighborhood::Moore);
+        }
+
+        // Two periods later, without searching again
+        let flown = finder.spaceships(8);
+
+        assert_eq!(
+            flown
+                .iter()
+                .map(|spaceship| spaceship.bounds)
+                .collect::<Vec<_>>(),
+            [(12, 12, 15, 15), (122, 100, 127, 104), (62, 198, 65, 201),]
+        );
+
+        for (spaceship, found) in flown.iter().zip(&found) {
+            assert_eq!(spaceship.speed(), found.speed());
+        }
+
+        // A whole sweep finds them where they were moved to
+        let mut swept = finder.search(grid.as_ref(), &rule, 8);
+
+        while !finder.queue.is_empty() {
+            swept = finder.search(grid.as_ref(), &rule, 8);
+        }
+
+        assert_eq!(summary(&swept), summary(&flown));
+        assert_eq!(
+            summary(&SpaceshipFinder::default().find(grid.as_ref(), &rule, 8)),
+            summary(&flown)
+        );
+    }
+
+    #[test]
+    fn forgets_crashed_spaceships_once_swept() {
+        let rule = Rule::default();
+        let mut grid = grid(Backend::Packed);
+        let mut finder = SpaceshipFinder::default();
+
+        finder.find(grid.as_ref(), &rule, 0);
+
+        // Blocks in opposite corners keep the whole grid to sweep over
+        grid.clear();
+
+        for (x, y) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            grid.set(x, y, true);
+            grid.set(254 + x, 254 + y, true);
+        }
+
+        assert_eq!(finder.spaceships(4).len(), 3);
+
+        finder.search(grid.as_ref(), &rule, 4);
+
+        while !finder.queue.is_empty() {
+            finder.search(grid.as_ref(), &rule, 4);
+        }
+
+        assert!(finder.spaceships(4).is_empty());
+    }
+}
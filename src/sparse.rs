@@ -0,0 +1,450 @@
+use std::collections::{HashMap, HashSet};
+
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+
+use crate::{
+    bitgrid::{diagonal_masks, step_word},
+    decay,
+    grid::{Grid, Neighborhood},
+    rule::Rule,
+    stats::Stats,
+    topology::Topology,
+};
+
+/// The width and height of a tile in cells.
+const TILE: i64 = 64;
+
+/// 64 rows of 64 cells, bit `i` of row `j` holding the cell at `x = i, y = j`
+/// relative to the tile's top left corner.
+type Tile = [u64; TILE as usize];
+
+/// An unbounded plane that only stores the tiles with alive cells in them.
+///
+/// Tiles are created when cells are born next to them and dropped as soon as
+/// they are empty, so the cost of a step only depends on how much is alive.
+/// Rules with B0 would fill the whole plane and can't be used.
+pub struct SparseGrid {
+    /// Tiles by their position in tiles, `(x / 64, y / 64)` rounded down.
+    tiles: HashMap<(i64, i64), Box<Tile>>,
+    /// The ages of the dying cells of the tiles that have any, `planes`
+    /// words for every row.
+    ages: HashMap<(i64, i64), Box<[u64]>>,
+    planes: usize,
+    /// The area that `fill` covers.
+    width: u32,
+    height: u32,
+    states: u8,
+}
+
+impl SparseGrid {
+    pub fn new(width: u32, height: u32, states: u8) -> Self {
+        Self {
+            tiles: HashMap::new(),
+            ages: HashMap::new(),
+            planes: decay::planes(states),
+            width,
+            height,
+            states,
+        }
+    }
+
+    /// The tile `(x, y)` is in and its position inside that tile.
+    fn locate(x: i64, y: i64) -> ((i64, i64), usize, u32) {
+        (
+            (x.div_euclid(TILE), y.div_euclid(TILE)),
+            y.rem_euclid(TILE) as usize,
+            x.rem_euclid(TILE) as u32,
+        )
+    }
+
+    /// The tiles that might have alive or dying cells next generation: every
+    /// tile with alive cells, its neighbors on the sides where it has alive
+    /// cells on the edge, and every tile with dying cells.
+    fn active_tiles(&self) -> HashSet<(i64, i64)> {
+        let mut active = HashSet::with_capacity(self.tiles.len() * 2);
+        active.extend(self.ages.keys());
+
+        for (&(x, y), tile) in &self.tiles {
+            let top = tile[0];
+            let bottom = tile[TILE as usize - 1];
+            let left = tile.iter().any(|row| row & 1 != 0);
+            let right = tile.iter().any(|row| row >> 63 != 0);
+
+            let neighbors = [
+                (-1, -1, top & 1 != 0),
+                (0, -1, top != 0),
+                (1, -1, top >> 63 != 0),
+                (-1, 0, left),
+                (0, 0, true),
+                (1, 0, right),
+                (-1, 1, bottom & 1 != 0),
+                (0, 1, bottom != 0),
+                (1, 1, bottom >> 63 != 0),
+            ];
+
+            for (dx, dy, needed) in neighbors {
+                if needed {
+                    active.insert((x + dx, y + dy));
+                }
+            }
+        }
+
+        active
+    }
+
+    /// The next generation of the tile at `(x, y)`, without its dying cells.
+    fn step_tile(&self, (x, y): (i64, i64), rule: &Rule, neighborhood: Neighborhood) -> Tile {
+        // The tile and its eight neighbors, `tiles[dy + 1][dx + 1]`
+        let tiles: [[Option<&Tile>; 3]; 3] = std::array::from_fn(|dy| {
+            std::array::from_fn(|dx| {
+                self.tiles
+                    .get(&(x + dx as i64 - 1, y + dy as i64 - 1))
+                    .map(|tile| &**tile)
+            })
+        });
+
+        // Row `row` of the column of tiles `column`, where rows -1 and 64 are
+        // in the tiles above and below
+        let word = |column: usize, row: i64| {
+            let (tile, row) = match row {
+                -1 => (tiles[0][column], TILE - 1),
+                TILE => (tiles[2][column], 0),
+                row => (tiles[1][column], row),
+            };
+
+            tile.map_or(0, |tile| tile[row as usize])
+        };
+
+        std::array::from_fn(|row| {
+            let shifted = [-1, 0, 1].map(|dy| {
+                let row = row as i64 + dy;
+                let (left, cells, right) = (word(0, row), word(1, row), word(2, row));
+
+                (
+                    (cells << 1) | (left >> 63),
+                    cells,
+                    (cells >> 1) | (right << 63),
+                )
+            });
+
+            step_word(
+                shifted,
+                rule,
+                diagonal_masks(neighborhood, y * TILE + row as i64),
+            )
+        })
+    }
+
+    /// The next generation of the ages of the tile at `position`, which went
+    /// from `old` to `new`, blocking births in `new` where cells are dying.
+    /// `None` if nothing is dying in it any more.
+    fn step_ages(&self, position: (i64, i64), old: &Tile, new: &mut Tile) -> Option<Box<[u64]>> {
+        if self.planes == 0 {
+            return None;
+        }
+
+        let mut ages = self
+            .ages
+            .get(&position)
+            .cloned()
+            .unwrap_or_else(|| vec![0; TILE as usize * self.planes].into_boxed_slice());
+
+        for (row, ages) in ages.chunks_exact_mut(self.planes).enumerate() {
+            // Dying cells can't be born until they are dead
+            new[row] &= !decay::dying(ages);
+            decay::step(ages, old[row] & !new[row], self.states);
+        }
+
+        ages.iter().any(|plane| *plane != 0).then_some(ages)
+    }
+}
+
+impl Grid for SparseGrid {
+    fn fill(&mut self, f: &(dyn Fn(u32, u32) -> bool + Sync)) {
+        let (width, height) = (self.width as i64, self.height as i64);
+
+        let positions: Vec<(i64, i64)> = (0..(height + TILE - 1) / TILE)
+            .flat_map(|y| (0..(width + TILE - 1) / TILE).map(move |x| (x, y)))
+            .collect();
+
+        self.tiles = positions
+            .into_par_iter()
+            .filter_map(|(tile_x, tile_y)| {
+                let tile: Tile = std::array::from_fn(|row| {
+                    let y = tile_y * TILE + row as i64;
+                    let mut word = 0;
+
+                    for bit in 0..TILE {
+                        let x = tile_x * TILE + bit;
+
+                        if x < width && y < height && f(x as u32, y as u32) {
+                            word |= 1 << bit;
+                        }
+                    }
+
+                    word
+                });
+
+                tile.iter()
+                    .any(|row| *row != 0)
+                    .then(|| ((tile_x, tile_y), Box::new(tile)))
+            })
+            .collect();
+
+        self.ages.clear();
+    }
+
+    /// Drops every tile, without going over the area `fill` covers.
+    fn clear(&mut self) {
+        self.tiles.clear();
+        self.ages.clear();
+    }
+
+    fn copy_row(&self, y: i64, left: i64, row: &mut [bool]) {
+        row.fill(false);
+
+        let mut x = left;
+
+        while x < left + row.len() as i64 {
+            let (position, tile_row, bit) = Self::locate(x, y);
+            let run = ((TILE - bit as i64) as usize).min((left + row.len() as i64 - x) as usize);
+            let start = (x - left) as usize;
+
+            if let Some(tile) = self.tiles.get(&position) {
+                let word = tile[tile_row] >> bit;
+
+                for (i, cell) in row[start..start + run].iter_mut().enumerate() {
+                    *cell = (word >> i) & 1 == 1;
+                }
+            }
+
+            x += run as i64;
+        }
+    }
+
+    fn copy_states(&self, y: i64, left: i64, row: &mut [u8]) {
+        for (x, state) in (left..).zip(row.iter_mut()) {
+            let (position, tile_row, bit) = Self::locate(x, y);
+            let alive = self.tiles.get(&position).map_or(0, |tile| tile[tile_row]);
+
+            *state = match self.ages.get(&position) {
+                Some(ages) => decay::state(
+                    alive,
+                    &ages[tile_row * self.planes..(tile_row + 1) * self.planes],
+                    bit,
+                ),
+                None => ((alive >> bit) & 1) as u8,
+            };
+        }
+    }
+
+    fn states(&self) -> u8 {
+        self.states
+    }
+
+    fn bounds(&self) -> (i64, i64, u64, u64) {
+        if self.tiles.is_empty() {
+            return (0, 0, 0, 0);
+        }
+
+        let (mut left, mut top) = (i64::MAX, i64::MAX);
+        let (mut right, mut bottom) = (i64::MIN, i64::MIN);
+
+        for &(x, y) in self.tiles.keys() {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x + 1);
+            bottom = bottom.max(y + 1);
+        }
+
+        (
+            left * TILE,
+            top * TILE,
+            ((right - left) * TILE) as u64,
+            ((bottom - top) * TILE) as u64,
+        )
+    }
+
+    fn regions(&self) -> Vec<(i64, i64, u64, u64)> {
+        let mut positions: Vec<_> = self.tiles.keys().chain(self.ages.keys()).collect();
+        positions.sort_unstable();
+        positions.dedup();
+
+        positions
+            .into_iter()
+            .map(|&(x, y)| (x * TILE, y * TILE, TILE as u64, TILE as u64))
+            .collect()
+    }
+
+    fn set(&mut self, x: i64, y: i64, alive: bool) {
+        let (position, row, bit) = Self::locate(x, y);
+
+        if let Some(ages) = self.ages.get_mut(&position) {
+            for plane in &mut ages[row * self.planes..(row + 1) * self.planes] {
+                *plane &= !(1 << bit);
+            }
+
+            if ages.iter().all(|plane| *plane == 0) {
+                self.ages.remove(&position);
+            }
+        }
+
+        if alive {
+            self.tiles
+                .entry(position)
+                .or_insert_with(|| Box::new([0; TILE as usize]))[row] |= 1 << bit;
+        } else if let Some(tile) = self.tiles.get_mut(&position) {
+            tile[row] &= !(1 << bit);
+
+            if tile.iter().all(|row| *row == 0) {
+                self.tiles.remove(&position);
+            }
+        }
+    }
+
+    fn population(&self) -> u64 {
+        self.tiles
+            .par_iter()
+            .map(|(_, tile)| tile.iter().map(|row| row.count_ones() as u64).sum::<u64>())
+            .sum()
+    }
+
+    /// Steps the plane. There are no edges, so `topology` is ignored.
+    fn step(&mut self, rule: &Rule, _topology: Topology, neighborhood: Neighborhood) -> Stats {
+        let active: Vec<(i64, i64)> = self.active_tiles().into_iter().collect();
+
+        let stepped: Vec<_> = active
+            .into_par_iter()
+            .map(|position| {
+                let mut tile = self.step_tile(position, rule, neighborhood);
+                let old = self
+                    .tiles
+                    .get(&position)
+                    .map_or([0; TILE as usize], |tile| **tile);
+                let ages = self.step_ages(position, &old, &mut tile);
+
+                let stats = (0..TILE)
+                    .map(|row| {
+                        let index = row as usize;
+                        Stats::of_words(
+                            position.1 * TILE + row,
+                            position.0 * TILE,
+                            &old[index..=index],
+                            &tile[index..=index],
+                        )
+                    })
+                    .fold(Stats::default(), Stats::merge);
+
+                (position, tile, ages, stats)
+            })
+            .collect();
+
+        let stats = stepped
+            .iter()
+            .fold(Stats::default(), |total, (.., stats)| total.merge(*stats));
+
+        self.ages = HashMap::new();
+        self.tiles = HashMap::new();
+
+        for (position, tile, ages, _) in stepped {
+            if let Some(ages) = ages {
+                self.ages.insert(position, ages);
+            }
+
+            if tile.iter().any(|row| *row != 0) {
+                self.tiles.insert(position, Box::new(tile));
+            }
+        }
+
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        color::Profile,
+        grid::{alive_cells, Backend},
+        life::{splitmix64, Config, GameOfLife, Size},
+        pattern::{Format, Pattern},
+        subpixel::Layout,
+    };
+
+    /// A game on `backend` starting from a random 100 by 100 soup in the
+    /// middle of a 512 by 512 grid, far enough from the edges that nothing
+    /// reaches them for hundreds of generations.
+    fn soup_game(backend: Backend, rule: &str) -> GameOfLife {
+        let cells: Vec<(i64, i64)> = (0..100 * 100)
+            .filter(|index| splitmix64(*index as u64).is_multiple_of(3))
+            .map(|index| (index % 100, index / 100))
+            .collect();
+
+        let config = Config {
+            rule: rule.parse().unwrap(),
+            topology: Topology::Plane,
+            backend,
+            size: Size::Cells(512, 512),
+            layout: Layout::default(),
+            profile: Profile::default(),
+            density: 0.5,
+            seed: Some(1),
+            pattern: Some(Pattern::from_cells(&cells, None, None)),
+            offset: Some((206, 206)),
+            reseed: false,
+        };
+
+        let mut game = GameOfLife::new(512, 512, &config);
+        game.populate(&config);
+        game
+    }
+
+    #[test]
+    fn soup_matches_packed() {
+        // Brian's Brain spreads at the speed of light, so it gets fewer
+        // generations before it would reach the edges
+        for (rule, generations) in [("B3/S23", 300), ("B36/S23", 300), ("B2/S/C3", 100)] {
+            let mut sparse = soup_game(Backend::Sparse, rule);
+            let mut packed = soup_game(Backend::Packed, rule);
+            assert_eq!(sparse.stats, packed.stats, "{rule}");
+
+            for generation in 1..=generations {
+                sparse.step();
+                packed.step();
+
+                assert_eq!(
+                    sparse.stats, packed.stats,
+                    "{rule}, generation {generation}"
+                );
+                assert_eq!(sparse.grid.population(), packed.grid.population());
+                assert_eq!(sparse.cycle, packed.cycle);
+            }
+
+            let (left, top, right, bottom) = packed.stats.bounds.unwrap();
+            assert!(left > 64 && top > 64 && right < 448 && bottom < 448);
+
+            // Macrocell patterns are quadtrees lined up with the grid, which
+            // differ between backends, so they are compared by their cells
+            for format in [Format::Rle, Format::Macrocell] {
+                assert_eq!(
+                    sparse.to_pattern(format).to_rle().unwrap(),
+                    packed.to_pattern(format).to_rle().unwrap(),
+                    "{rule} as {format:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bounds_of_far_apart_tiles_dont_wrap() {
+        let mut grid = SparseGrid::new(0, 0, 2);
+        grid.set(-(1 << 40), 3, true);
+        grid.set(1 << 40, 1 << 35, true);
+
+        assert_eq!(
+            grid.bounds(),
+            (-(1 << 40), 0, (1 << 41) + 64, (1 << 35) + 64)
+        );
+        assert_eq!(alive_cells(&grid), [(-(1 << 40), 3), (1 << 40, 1 << 35)]);
+    }
+}
@@ -0,0 +1,314 @@
+use std::{fmt::Write, io, path::Path};
+
+use crate::grid::Grid;
+
+/// Counts describing one generation, gathered by `Grid::step` in the same
+/// parallel pass that computes it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub population: u64,
+    /// Cells that came alive in this generation.
+    pub births: u64,
+    /// Cells that died in this generation.
+    pub deaths: u64,
+    /// The smallest rectangle `(left, top, right, bottom)` containing every
+    /// alive cell, with `right` and `bottom` just past it, or `None` when
+    /// nothing is alive.
+    pub bounds: Option<(i64, i64, i64, i64)>,
+    /// A hash of the alive cells and where they are, for spotting repeats.
+    pub hash: u64,
+}
+
+impl Stats {
+    /// Combines the stats of two separate parts of a grid.
+    pub fn merge(self, other: Self) -> Self {
+        let bounds = match (self.bounds, other.bounds) {
+            (Some(a), Some(b)) => Some((a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))),
+            (a, b) => a.or(b),
+        };
+
+        Self {
+            population: self.population + other.population,
+            births: self.births + other.births,
+            deaths: self.deaths + other.deaths,
+            bounds,
+            hash: self.hash.wrapping_add(other.hash),
+        }
+    }
+
+    /// The stats of row `y` stepped from `old` to `new`, one cell per `bool`
+    /// starting at `x = left`.
+    pub fn of_cells(y: i64, left: i64, old: &[bool], new: &[bool]) -> Self {
+        let mut stats = Self::default();
+
+        // Hashed as 64 cell words so that every backend agrees
+        let mut key = position_key(left, y);
+
+        for chunk in new.chunks(64) {
+            let word = chunk
+                .iter()
+                .enumerate()
+                .fold(0, |word, (x, cell)| word | ((*cell as u64) << x));
+            stats.hash = stats.hash.wrapping_add(hash_word(key, word));
+            key = key.wrapping_add(WORD_KEY_STEP);
+        }
+
+        for (old, new) in old.iter().zip(new) {
+            stats.population += *new as u64;
+            stats.births += (*new && !old) as u64;
+            stats.deaths += (*old && !new) as u64;
+        }
+
+        if let Some(first) = new.iter().position(|cell| *cell) {
+            let last = new.iter().rposition(|cell| *cell).unwrap();
+            stats.bounds = Some((left + first as i64, y, left + last as i64 + 1, y + 1));
+        }
+
+        stats
+    }
+
+    /// The stats of row `y` stepped from `old` to `new`, 64 cells per word
+    /// starting at `x = left`.
+    pub fn of_words(y: i64, left: i64, old: &[u64], new: &[u64]) -> Self {
+        // Separate passes so that each of them can be vectorized
+        let mut stats = Self {
+            population: count_ones(new.iter().copied()),
+            births: count_ones(old.iter().zip(new).map(|(old, new)| new & !old)),
+            deaths: count_ones(old.iter().zip(new).map(|(old, new)| old & !new)),
+            ..Self::default()
+        };
+
+        let mut key = position_key(left, y);
+
+        for word in new {
+            stats.hash = stats.hash.wrapping_add(hash_word(key, *word));
+            key = key.wrapping_add(WORD_KEY_STEP);
+        }
+
+        if let Some(first) = new.iter().position(|word| *word != 0) {
+            let last = new.iter().rposition(|word| *word != 0).unwrap();
+
+            stats.bounds = Some((
+                left + first as i64 * 64 + new[first].trailing_zeros() as i64,
+                y,
+                left + last as i64 * 64 + 64 - new[last].leading_zeros() as i64,
+                y + 1,
+            ));
+        }
+
+        stats
+    }
+
+    /// The population and bounds of a grid as it is, without births or deaths.
+    pub fn of_grid(grid: &dyn Grid) -> Self {
+        grid.regions()
+            .into_iter()
+            .flat_map(|(left, top, width, height)| {
+                let mut row = vec![false; width as usize];
+
+                (top..top + height as i64).map(move |y| {
+                    grid.copy_row(y, left, &mut row);
+
+                    // Compared with itself, so nothing is born or dies
+                    Self::of_cells(y, left, &row, &row)
+                })
+            })
+            .fold(Self::default(), Self::merge)
+    }
+
+    /// The width and height of `bounds`, 0 by 0 when nothing is alive.
+    pub fn size(&self) -> (u64, u64) {
+        self.bounds.map_or((0, 0), |(left, top, right, bottom)| {
+            ((right - left) as u64, (bottom - top) as u64)
+        })
+    }
+}
+
+fn count_ones(words: impl Iterator<Item = u64>) -> u64 {
+    words.map(|word| word.count_ones() as u64).sum()
+}
+
+/// Multiplies x positions in `position_key`.
+const X_KEY: u64 = 0x9E3779B97F4A7C15;
+
+/// How much the key of a word changes from one word to the next along a row.
+const WORD_KEY_STEP: u64 = X_KEY.wrapping_mul(64);
+
+/// A number standing for the cell at `(x, y)`, different for every position.
+/// The two parts are added so that stepping the key by `WORD_KEY_STEP` gives
+/// the key 64 cells to the right, whichever word a row is split at.
+fn position_key(x: i64, y: i64) -> u64 {
+    (x as u64)
+        .wrapping_mul(X_KEY)
+        .wrapping_add((y as u64).wrapping_mul(0xC2B2AE3D27D4EB4F))
+}
+
+/// The hash of a word of 64 cells starting at the position of `key`, 0 when
+/// they are all dead so that empty space doesn't count. Hashes of separate
+/// words are summed, which doesn't depend on the order they are visited in, so
+/// every bit has to be mixed thoroughly for sums not to cancel out.
+fn hash_word(key: u64, word: u64) -> u64 {
+    // MurmurHash3's finalizer
+    let mut hash = word ^ key;
+    hash = (hash ^ (hash >> 33)).wrapping_mul(0xFF51AFD7ED558CCD);
+    hash = (hash ^ (hash >> 33)).wrapping_mul(0xC4CEB9FE1A85EC53);
+    hash ^= hash >> 33;
+
+    // Branch free, this runs far too often to risk mispredictions
+    hash * (word != 0) as u64
+}
+
+/// Saves the stats of a run by generation, as JSON if `path` ends in `.json`
+/// and as CSV otherwise.
+pub fn save_series(path: &Path, series: &[(u64, Stats)]) -> io::Result<()> {
+    let json = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("json"));
+
+    std::fs::write(
+        path,
+        if json {
+            to_json(series)
+        } else {
+            to_csv(series)
+        },
+    )
+}
+
+fn to_csv(series: &[(u64, Stats)]) -> String {
+    let mut csv = String::from("generation,population,births,deaths,left,top,width,height\n");
+
+    for (generation, stats) in series {
+        let (left, top) = stats.bounds.map_or((0, 0), |(left, top, ..)| (left, top));
+        let (width, height) = stats.size();
+
+        writeln!(
+            csv,
+            "{generation},{},{},{},{left},{top},{width},{height}",
+            stats.population, stats.births, stats.deaths
+        )
+        .unwrap();
+    }
+
+    csv
+}
+
+fn to_json(series: &[(u64, Stats)]) -> String {
+    let mut json = String::from("[\n");
+
+    for (index, (generation, stats)) in series.iter().enumerate() {
+        let bounds = match stats.bounds {
+            Some((left, top, ..)) => {
+                let (width, height) = stats.size();
+                format!("{{\"left\": {left}, \"top\": {top}, \"width\": {width}, \"height\": {height}}}")
+            }
+            None => "null".to_string(),
+        };
+
+        write!(
+            json,
+            "  {{\"generation\": {generation}, \"population\": {}, \"births\": {}, \"deaths\": {}, \"bounds\": {bounds}}}",
+            stats.population, stats.births, stats.deaths
+        )
+        .unwrap();
+
+        json.push_str(if index + 1 < series.len() {
+            ",\n"
+        } else {
+            "\n"
+        });
+    }
+
+    json.push_str("]\n");
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{Backend, Neighborhood};
+    use crate::rule::Rule;
+    use crate::topology::Topology;
+
+    /// The stats of a blinker in a 10 by 10 grid from generation 0, lying
+    /// flat on row 4 to start with.
+    fn blinker_series(backend: Backend) -> Vec<(u64, Stats)> {
+        let mut grid = backend.create(10, 10, 2);
+        for x in 3..6 {
+            grid.set(x, 4, true);
+        }
+
+        let mut series = vec![(0, Stats::of_grid(grid.as_ref()))];
+
+        for generation in 1..=4 {
+            let stats = grid.step(&Rule::default(), Topology::Plane, Neighborhood::Moore);
+            series.push((generation, stats));
+        }
+
+        series
+    }
+
+    #[test]
+    fn blinker_on_every_backend() {
+        let flat = Some((3, 4, 6, 5));
+        let upright = Some((4, 3, 5, 6));
+
+        for backend in [Backend::Bytes, Backend::Packed, Backend::Sparse] {
+            let series = blinker_series(backend);
+
+            for (generation, stats) in &series {
+                let expected = if *generation == 0 { 0 } else { 2 };
+                let (bounds, size) = if generation % 2 == 0 {
+                    (flat, (3, 1))
+                } else {
+                    (upright, (1, 3))
+                };
+
+                assert_eq!(stats.population, 3, "{backend} {generation}");
+                assert_eq!(stats.births, expected, "{backend} {generation}");
+                assert_eq!(stats.deaths, expected, "{backend} {generation}");
+                assert_eq!(stats.bounds, bounds, "{backend} {generation}");
+                assert_eq!(stats.size(), size, "{backend} {generation}");
+            }
+
+            // The same phase in the same place hashes the same
+            assert_eq!(series[0].1.hash, series[2].1.hash);
+            assert_eq!(series[1].1.hash, series[3].1.hash);
+            assert_ne!(series[0].1.hash, series[1].1.hash);
+
+            assert_eq!(series, blinker_series(Backend::Packed), "{backend}");
+        }
+    }
+
+    #[test]
+    fn writes_csv_and_json() {
+        let mut series = blinker_series(Backend::Packed);
+        series.truncate(2);
+        series.push((
+            7,
+            Stats {
+                deaths: 3,
+                ..Stats::default()
+            },
+        ));
+
+        assert_eq!(
+            to_csv(&series),
+            "generation,population,births,deaths,left,top,width,height\n\
+             0,3,0,0,3,4,3,1\n\
+             1,3,2,2,4,3,1,3\n\
+             7,0,0,3,0,0,0,0\n"
+        );
+
+        assert_eq!(
+            to_json(&series),
+            "[\n  \
+             {\"generation\": 0, \"population\": 3, \"births\": 0, \"deaths\": 0, \"bounds\": {\"left\": 3, \"top\": 4, \"width\": 3, \"height\": 1}},\n  \
+             {\"generation\": 1, \"population\": 3, \"births\": 2, \"deaths\": 2, \"bounds\": {\"left\": 4, \"top\": 3, \"width\": 1, \"height\": 3}},\n  \
+             {\"generation\": 7, \"population\": 0, \"births\": 0, \"deaths\": 3, \"bounds\": null}\n\
+             ]\n"
+        );
+
+        assert_eq!(to_json(&[]), "[\n]\n");
+    }
+}
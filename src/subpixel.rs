@@ -0,0 +1,350 @@
+use std::{fmt, str::FromStr};
+
+use rayon::{
+    iter::{IndexedParallelIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+use crate::{
+    color::Pipeline,
+    grid::{Grid, Neighborhood},
+    viewport::Viewport,
+};
+
+/// The physical arrangement of the light emitters of a display, and how the
+/// life grid is laid over them with one cell per emitter.
+///
+/// The cells form a rectangular lattice in which every pixel covers the same
+/// block of `cells_per_pixel` cells, and the pattern of emitter colors repeats
+/// every `tile` cells.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Geometry {
+    /// The size of the repeating block of cells.
+    pub tile: (u32, u32),
+    /// The cells covered by a single pixel.
+    pub cells_per_pixel: (u32, u32),
+    /// How much each cell of the tile, in row major order, contributes to the
+    /// red, green and blue channels of its pixel when alive.
+    pub weights: Vec<[f32; 3]>,
+    /// Which cells count as neighbors, chosen so that neighboring cells are
+    /// neighboring emitters.
+    pub neighborhood: Neighborhood,
+}
+
+const RED: [f32; 3] = [1.0, 0.0, 0.0];
+const GREEN: [f32; 3] = [0.0, 1.0, 0.0];
+const BLUE: [f32; 3] = [0.0, 0.0, 1.0];
+const WHITE: [f32; 3] = [1.0, 1.0, 1.0];
+
+impl Geometry {
+    /// The grid size in cells that puts one cell on every emitter of a
+    /// `width` by `height` pixel window.
+    pub fn grid_size(&self, width: u32, height: u32) -> (u32, u32) {
+        (
+            width * self.cells_per_pixel.0,
+            height * self.cells_per_pixel.1,
+        )
+    }
+
+    /// The number of pixels needed to show a grid of `width` by `height` cells.
+    pub fn pixel_size(&self, width: u32, height: u32) -> (u32, u32) {
+        (
+            width.div_ceil(self.cells_per_pixel.0),
+            height.div_ceil(self.cells_per_pixel.1),
+        )
+    }
+
+    /// The channel weights of the cell at `(x, y)` in the grid.
+    pub fn weights(&self, x: u32, y: u32) -> [f32; 3] {
+        self.weights[((y % self.tile.1) * self.tile.0 + x % self.tile.0) as usize]
+    }
+}
+
+/// Draws the part of `grid` inside `viewport` into `pixels`, a `width` by
+/// `height` buffer of 0RGB pixels.
+pub fn render(
+    grid: &dyn Grid,
+    geometry: &Geometry,
+    viewport: &Viewport,
+    colors: &Pipeline,
+    pixels: &mut [u32],
+    width: u32,
+    height: u32,
+) {
+    let pixels = &mut pixels[..width as usize * height as usize];
+
+    match viewport.pixels_per_cell() {
+        Some(size) => render_pixels(grid, viewport, colors, pixels, width, size),
+        None => render_subpixels(grid, geometry, viewport, colors, pixels, width),
+    }
+}
+
+/// How bright a cell in `state` is drawn under a rule with `states` states,
+/// from 1 when alive down to nothing when dead, so that the dying cells of
+/// Generations rules fade out instead of snapping off.
+fn brightness(state: u8, states: u8) -> f32 {
+    match state {
+        0 => 0.0,
+        1 => 1.0,
+        dying => (states - dying) as f32 / (states - 1) as f32,
+    }
+}
+
+/// Draws every subpixel as bright as the average brightness of the square of
+/// cells behind it, which is a single cell at zoom 0.
+fn render_subpixels(
+    grid: &dyn Grid,
+    geometry: &Geometry,
+    viewport: &Viewport,
+    colors: &Pipeline,
+    pixels: &mut [u32],
+    width: u32,
+) {
+    let (cells_x, cells_y) = geometry.cells_per_pixel;
+    let block = viewport.cells_per_subpixel() as i64;
+    let (left, top) = viewport.origin(geometry);
+
+    let subpixels_x = (width * cells_x) as usize;
+    let area = (block * block) as f32;
+
+    let states = grid.states();
+    let shades: Vec<f32> = (0..states).map(|state| brightness(state, states)).collect();
+
+    // Only the cells that are both on screen and in the grid are read, so that
+    // zooming far out costs no more than the cells there are
+    let right = left + subpixels_x as i64 * block;
+    let regions: Vec<_> = grid
+        .regions()
+        .into_iter()
+        .filter_map(|(region_left, region_top, region_width, region_height)| {
+            let columns = region_left.max(left)..(region_left + region_width as i64).min(right);
+            let rows = region_top..region_top + region_height as i64;
+            (!columns.is_empty() && !rows.is_empty()).then_some((columns, rows))
+        })
+        .collect();
+
+    pixels
+        .par_chunks_mut(width as usize)
+        .enumerate()
+        .for_each(|(y, pixels)| {
+            // The total brightness of the cells behind every subpixel of this
+            // row of pixels, which is the number of alive cells unless some
+            // are dying
+            let mut counts = vec![0.0f32; subpixels_x * cells_y as usize];
+            let mut cells = Vec::new();
+            let mut cell_states = Vec::new();
+
+            let row_top = top + (y as u32 * cells_y) as i64 * block;
+            let row_bottom = row_top + cells_y as i64 * block;
+
+            for (columns, rows) in &regions {
+                let length = (columns.end - columns.start) as usize;
+                // Where the region starts in the subpixels of this row
+                let offset = (columns.start - left) as usize;
+
+                for cell_y in rows.start.max(row_top)..rows.end.min(row_bottom) {
+                    let subpixel_row = ((cell_y - row_top) / block) as usize;
+                    let counts = &mut counts[subpixel_row * subpixels_x..][..subpixels_x];
+
+                    if states > 2 {
+                        cell_states.resize(length, 0);
+                        grid.copy_states(cell_y, columns.start, &mut cell_states);
+
+                        for (x, state) in cell_states.iter().enumerate() {
+                            counts[(offset + x) / block as usize] += shades[*state as usize];
+                        }
+                    } else {
+                        cells.resize(length, false);
+                        grid.copy_row(cell_y, columns.start, &mut cells);
+
+                        for (x, cell) in cells.iter().enumerate() {
+                            if *cell {
+                                counts[(offset + x) / block as usize] += 1.0;
+                            }
+                        }
+                    }
+                }
+            }
+
+            for (x, pixel) in pixels.iter_mut().enumerate() {
+                // Light adds up linearly, the pipeline takes care of turning it
+                // into what the monitor needs to produce it
+                let mut light = [0.0; 3];
+
+                for row in 0..cells_y {
+                    for column in 0..cells_x {
+                        let subpixel_x = x as u32 * cells_x + column;
+                        let count = counts[row as usize * subpixels_x + subpixel_x as usize];
+
+                        if count > 0.0 {
+                            let weights = geometry.weights(subpixel_x, y as u32 * cells_y + row);
+                            let fraction = count / area;
+
+                            for (channel, weight) in light.iter_mut().zip(weights) {
+                                *channel += weight * fraction;
+                            }
+                        }
+                    }
+                }
+
+                *pixel = colors.pixel(light);
+            }
+        });
+}
+
+/// Draws every cell as a gray square `size` pixels wide, white when alive,
+/// ignoring the subpixels.
+fn render_pixels(
+    grid: &dyn Grid,
+    viewport: &Viewport,
+    colors: &Pipeline,
+    pixels: &mut [u32],
+    width: u32,
+    size: u32,
+) {
+    let states = grid.states();
+    let shades: Vec<u32> = (0..states)
+        .map(|state| match state {
+            0 => 0xFF000000,
+            state => colors.pixel([brightness(state, states); 3]),
+        })
+        .collect();
+
+    // The cells that are at least partly on screen
+    let left = viewport.x.floor() as i64;
+    let columns = (width / size + 2) as usize;
+
+    pixels
+        .par_chunks_mut(width as usize)
+        .enumerate()
+        .for_each(|(y, pixels)| {
+            let cell_y = (viewport.y + y as f64 / size as f64).floor() as i64;
+
+            let mut cells = vec![0; columns];
+            grid.copy_states(cell_y, left, &mut cells);
+
+            for (x, pixel) in pixels.iter_mut().enumerate() {
+                let cell_x = (viewport.x + x as f64 / size as f64).floor() as i64;
+                *pixel = shades[cells[(cell_x - left) as usize] as usize];
+            }
+        });
+}
+
+/// A named subpixel arrangement, see
+/// <https://geometrian.com/resources/subpixelzoo/> for what they look like.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// Vertical stripes, red on the left. The most common layout.
+    #[default]
+    Rgb,
+    /// Vertical stripes, blue on the left.
+    Bgr,
+    /// Horizontal stripes, red on top, as seen on most monitors rotated to portrait.
+    VRgb,
+    /// Horizontal stripes, blue on top.
+    VBgr,
+    /// PenTile RGBG, where every pixel has a green subpixel and either a red or
+    /// a blue one, alternating in a checkerboard.
+    Rgbg,
+    /// Delta, where every other row is shifted by half a subpixel so that the
+    /// subpixels form triangles. Every cell has six neighbors.
+    Delta,
+    /// Vertical stripes with an extra white subpixel, red, green, blue, white.
+    Rgbw,
+}
+
+impl Layout {
+    pub const ALL: [Self; 7] = [
+        Self::Rgb,
+        Self::Bgr,
+        Self::VRgb,
+        Self::VBgr,
+        Self::Rgbg,
+        Self::Delta,
+        Self::Rgbw,
+    ];
+
+    pub fn geometry(self) -> Geometry {
+        let (tile, cells_per_pixel, weights, neighborhood) = match self {
+            Self::Rgb => ((3, 1), (3, 1), vec![RED, GREEN, BLUE], Neighborhood::Moore),
+            Self::Bgr => ((3, 1), (3, 1), vec![BLUE, GREEN, RED], Neighborhood::Moore),
+            Self::VRgb => ((1, 3), (1, 3), vec![RED, GREEN, BLUE], Neighborhood::Moore),
+            Self::VBgr => ((1, 3), (1, 3), vec![BLUE, GREEN, RED], Neighborhood::Moore),
+            Self::Rgbg => (
+                (4, 2),
+                (2, 1),
+                vec![RED, GREEN, BLUE, GREEN, BLUE, GREEN, RED, GREEN],
+                Neighborhood::Moore,
+            ),
+            // Each cell of an odd row sits between two cells of the rows around
+            // it and has a different color from both
+            Self::Delta => (
+                (3, 2),
+                (3, 1),
+                vec![RED, GREEN, BLUE, BLUE, RED, GREEN],
+                Neighborhood::Hexagonal,
+            ),
+            Self::Rgbw => (
+                (4, 1),
+                (4, 1),
+                vec![RED, GREEN, BLUE, WHITE],
+                Neighborhood::Moore,
+            ),
+        };
+
+        Geometry {
+            tile,
+            cells_per_pixel,
+            weights,
+            neighborhood,
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Rgb => "rgb",
+            Self::Bgr => "bgr",
+            Self::VRgb => "v-rgb",
+            Self::VBgr => "v-bgr",
+            Self::Rgbg => "rgbg",
+            Self::Delta => "delta",
+            Self::Rgbw => "rgbw",
+        };
+
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayoutParseError(String);
+
+impl fmt::Display for LayoutParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown subpixel layout \"{}\", expected rgb, bgr, v-rgb, v-bgr, rgbg, delta or rgbw",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for LayoutParseError {}
+
+impl FromStr for Layout {
+    type Err = LayoutParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "rgb" => Ok(Self::Rgb),
+            "bgr" => Ok(Self::Bgr),
+            "v-rgb" | "vrgb" => Ok(Self::VRgb),
+            "v-bgr" | "vbgr" => Ok(Self::VBgr),
+            "rgbg" | "pentile" => Ok(Self::Rgbg),
+            "delta" => Ok(Self::Delta),
+            "rgbw" => Ok(Self::Rgbw),
+            _ => Err(LayoutParseError(s.to_string())),
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::{fmt, str::FromStr};
+
+/// How the edges of a bounded grid are joined, following Golly's bounded grid
+/// notation (`P`, `T`, `K`, `C` and `S`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Topology {
+    /// Everything outside the grid is dead.
+    #[default]
+    Plane,
+    /// Left joins right and top joins bottom.
+    Torus,
+    /// Like a torus, but the top and bottom edges are joined with a twist, so
+    /// something leaving through the top reappears mirrored at the bottom.
+    KleinBottle,
+    /// Both pairs of opposite edges are joined with a twist.
+    CrossSurface,
+    /// The top edge joins the left edge and the bottom edge joins the right
+    /// edge. Golly only allows square sphere grids, here a non-square grid is
+    /// joined proportionally along the edges.
+    Sphere,
+}
+
+impl Topology {
+    /// Maps a cell position that may lie up to one cell outside the grid to the
+    /// cell it refers to, or `None` if it is dead border.
+    ///
+    /// Corner positions of the cross-surface and sphere are singular points
+    /// where the joined edges meet and are always treated as dead.
+    pub fn resolve(self, x: i64, y: i64, width: u32, height: u32) -> Option<(u32, u32)> {
+        let (w, h) = (width as i64, height as i64);
+
+        let x_outside = x < 0 || x >= w;
+        let y_outside = y < 0 || y >= h;
+
+        if !x_outside && !y_outside {
+            return Some((x as u32, y as u32));
+        }
+
+        let (x, y) = match self {
+            Self::Plane => return None,
+            Self::Torus => (x.rem_euclid(w), y.rem_euclid(h)),
+            Self::KleinBottle => {
+                let x = if y_outside { w - 1 - x } else { x };
+                (x.rem_euclid(w), y.rem_euclid(h))
+            }
+            Self::CrossSurface => match (x_outside, y_outside) {
+                (true, true) => return None,
+                (true, false) => (x.rem_euclid(w), h - 1 - y),
+                (false, true) => (w - 1 - x, y.rem_euclid(h)),
+                (false, false) => unreachable!(),
+            },
+            Self::Sphere => match (x < 0, x >= w, y < 0, y >= h) {
+                // Above the top edge, which is joined to the left edge
+                (false, false, true, false) => (0, x * h / w),
+                // Left of the left edge, which is joined to the top edge
+                (true, false, false, false) => (y * w / h, 0),
+                // Below the bottom edge, which is joined to the right edge
+                (false, false, false, true) => (w - 1, x * h / w),
+                // Right of the right edge, which is joined to the bottom edge
+                (false, true, false, false) => (y * w / h, h - 1),
+                _ => return None,
+            },
+        };
+
+        Some((x as u32, y as u32))
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let letter = match self {
+            Self::Plane => "P",
+            Self::Torus => "T",
+            Self::KleinBottle => "K",
+            Self::CrossSurface => "C",
+            Self::Sphere => "S",
+        };
+
+        write!(f, "{letter}")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TopologyParseError(String);
+
+impl fmt::Display for TopologyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown topology \"{}\", expected one of P (plane), T (torus), K (Klein bottle), C (cross-surface) or S (sphere)",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for TopologyParseError {}
+
+impl FromStr for Topology {
+    type Err = TopologyParseError;
+
+    /// Accepts the letters and names of the topologies, followed by the size
+    /// of Golly's bounded grids as in `T100,80` or `K100*,80`, which is
+    /// ignored as the grid gets its size from `--size`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (name, size) = s.split_at(s.find(|c: char| c.is_ascii_digit()).unwrap_or(s.len()));
+
+        let size_character = |c: char| c.is_ascii_digit() || ",*+-".contains(c);
+
+        if !size.chars().all(size_character) {
+            return Err(TopologyParseError(s.to_string()));
+        }
+
+        match name.to_ascii_lowercase().as_str() {
+            "p" | "plane" => Ok(Self::Plane),
+            "t" | "torus" => Ok(Self::Torus),
+            "k" | "klein" | "klein-bottle" => Ok(Self::KleinBottle),
+            "c" | "cross" | "cross-surface" => Ok(Self::CrossSurface),
+            "s" | "sphere" => Ok(Self::Sphere),
+            _ => Err(TopologyParseError(s.to_string())),
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use crate::subpixel::Geometry;
+
+/// Which part of the grid the window shows, and at what size.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Viewport {
+    /// The grid position at the top left corner of the window, in cells.
+    pub x: f64,
+    pub y: f64,
+    /// 0 puts one cell on every subpixel. Every step below halves the cells, so
+    /// that each subpixel shows the average of a square of them, and every step
+    /// above doubles them, drawing each cell as a square of whole pixels from 1
+    /// on.
+    pub zoom: i32,
+}
+
+impl Viewport {
+    pub const MIN_ZOOM: i32 = -10;
+    pub const MAX_ZOOM: i32 = 7;
+
+    /// The width of the square of cells every subpixel shows when zoomed out,
+    /// 1 at zoom 0 and above.
+    pub fn cells_per_subpixel(&self) -> u32 {
+        1 << (-self.zoom).max(0)
+    }
+
+    /// The width of every cell in pixels when zoomed in, `None` at zoom 0 and below.
+    pub fn pixels_per_cell(&self) -> Option<u32> {
+        (self.zoom > 0).then(|| 1 << (self.zoom - 1))
+    }
+
+    /// How many cells one pixel spans horizontally and vertically.
+    pub fn scale(&self, geometry: &Geometry) -> (f64, f64) {
+        match self.pixels_per_cell() {
+            Some(pixels) => (1.0 / pixels as f64, 1.0 / pixels as f64),
+            None => {
+                let cells = self.cells_per_subpixel();
+                (
+                    (geometry.cells_per_pixel.0 * cells) as f64,
+                    (geometry.cells_per_pixel.1 * cells) as f64,
+                )
+            }
+        }
+    }
+
+    /// The grid cell drawn at the top left corner of the window when zoomed
+    /// out. It is kept to a multiple of the tile, so that panning never
+    /// changes which color of subpixel a cell is drawn on.
+    pub fn origin(&self, geometry: &Geometry) -> (i64, i64) {
+        let block = self.cells_per_subpixel() as i64;
+        let (tile_width, tile_height) = (
+            geometry.tile.0 as i64 * block,
+            geometry.tile.1 as i64 * block,
+        );
+
+        (
+            (self.x.floor() as i64).div_euclid(tile_width) * tile_width,
+            (self.y.floor() as i64).div_euclid(tile_height) * tile_height,
+        )
+    }
+
+    /// The grid position under `(x, y)` in the window, in cells.
+    pub fn cell_at(&self, geometry: &Geometry, (x, y): (f64, f64)) -> (f64, f64) {
+        let (scale_x, scale_y) = self.scale(geometry);
+
+        let (left, top) = match self.pixels_per_cell() {
+            Some(_) => (self.x, self.y),
+            None => {
+                let (left, top) = self.origin(geometry);
+                (left as f64, top as f64)
+            }
+        };
+
+        (left + x * scale_x, top + y * scale_y)
+    }
+
+    /// Where the grid position `(x, y)` in cells is drawn in the window, in
+    /// pixels. The opposite of `cell_at`.
+    pub fn pixel_at(&self, geometry: &Geometry, (x, y): (f64, f64)) -> (f64, f64) {
+        let (scale_x, scale_y) = self.scale(geometry);
+        let (left, top) = self.cell_at(geometry, (0.0, 0.0));
+
+        ((x - left) / scale_x, (y - top) / scale_y)
+    }
+
+    /// Moves the grid along with a drag of `(dx, dy)` pixels.
+    pub fn pan(&mut self, geometry: &Geometry, dx: f64, dy: f64) {
+        let (scale_x, scale_y) = self.scale(geometry);
+        self.x -= dx * scale_x;
+        self.y -= dy * scale_y;
+    }
+
+    /// Zooms in by `steps`, or out for negative steps, keeping the cell under
+    /// `position` in the window where it is.
+    pub fn zoom_at(&mut self, geometry: &Geometry, position: (f64, f64), steps: i32) {
+        let (x, y) = self.cell_at(geometry, position);
+
+        self.zoom = (self.zoom + steps).clamp(Self::MIN_ZOOM, Self::MAX_ZOOM);
+
+        let (scale_x, scale_y) = self.scale(geometry);
+        self.x = x - position.0 * scale_x;
+        self.y = y - position.1 * scale_y;
+    }
+}